@@ -0,0 +1,65 @@
+use std::{net::IpAddr, str::FromStr};
+
+use anyhow::{Result, bail};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        match (self.network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(*address) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(*address) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address.parse::<IpAddr>()?, Some(prefix.parse::<u8>()?)),
+            None => (s.parse::<IpAddr>()?, None),
+        };
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max_prefix);
+
+        if prefix > max_prefix {
+            bail!("Invalid network {s}: prefix longer than {max_prefix} bits");
+        }
+
+        Ok(Cidr { network: address, prefix })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_match() -> Result<()> {
+        let lan: Cidr = "192.168.1.0/24".parse()?;
+
+        assert!(lan.contains(&"192.168.1.77".parse()?));
+        assert!(!lan.contains(&"192.168.2.1".parse()?));
+        assert!(!lan.contains(&"::1".parse()?));
+        assert!("0.0.0.0/0".parse::<Cidr>()?.contains(&"8.8.8.8".parse()?));
+        assert!("fd00::/8".parse::<Cidr>()?.contains(&"fd12::1".parse()?));
+        assert!("10.0.0.1".parse::<Cidr>()?.contains(&"10.0.0.1".parse()?));
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("not-a-network/8".parse::<Cidr>().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use std::{collections::HashMap, time::{Duration, Instant}};
+
+use crate::{common::{Name, QClass, QType, ResponseCode}, message::Answer};
+
+pub type CacheKey = (Name, QType, QClass);
+
+#[derive(Debug)]
+struct CacheEntry {
+    answers: Vec<Answer>,
+    inserted: Instant,
+    deadline: Instant,
+    last_used: u64,
+    refreshing: bool,
+}
+
+#[derive(Debug)]
+pub struct Cache {
+    capacity: usize,
+    entries: HashMap<CacheKey, CacheEntry>,
+    clock: u64,
+}
+
+impl Cache {
+    pub fn new(capacity: usize) -> Self {
+        Cache {
+            capacity,
+            entries: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&mut self, key: &CacheKey) -> Option<Vec<Answer>> {
+        let now = Instant::now();
+        let tick = self.tick();
+        let entry = self.entries.get_mut(key)?;
+
+        if entry.deadline <= now {
+            self.entries.remove(key);
+            return None;
+        }
+
+        entry.last_used = tick;
+        let elapsed = (now - entry.inserted).as_secs() as u32;
+        Some(entry.answers
+                  .iter()
+                  .map(|a| a.with_ttl(a.ttl().saturating_sub(elapsed)))
+                  .collect())
+    }
+
+    pub fn claim_refresh(&mut self, key: &CacheKey, threshold: f64) -> bool {
+        let now = Instant::now();
+        let Some(entry) = self.entries.get_mut(key) else {
+            return false;
+        };
+        let lifetime = (entry.deadline - entry.inserted).as_secs_f64();
+        let remaining = entry.deadline.saturating_duration_since(now).as_secs_f64();
+
+        if entry.refreshing || remaining >= lifetime * threshold {
+            return false;
+        }
+
+        entry.refreshing = true;
+        true
+    }
+
+    pub fn insert(&mut self, key: CacheKey, answers: Vec<Answer>) {
+        let Some(ttl) = answers.iter().map(|a| a.ttl()).min() else {
+            return;
+        };
+
+        if self.capacity == 0 || ttl == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            self.evict();
+        }
+
+        let inserted = Instant::now();
+        let last_used = self.tick();
+        self.entries.insert(key, CacheEntry {
+            answers,
+            inserted,
+            deadline: inserted + Duration::from_secs(ttl as u64),
+            last_used,
+            refreshing: false,
+        });
+    }
+
+    fn evict(&mut self) {
+        let now = Instant::now();
+        self.entries.retain(|_, entry| entry.deadline > now);
+
+        if self.entries.len() >= self.capacity {
+            let oldest = self.entries
+                             .iter()
+                             .min_by_key(|(_, entry)| entry.last_used)
+                             .map(|(key, _)| key.clone());
+            if let Some(key) = oldest {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct NegativeEntry {
+    response_code: ResponseCode,
+    soa: Answer,
+    deadline: Instant,
+}
+
+#[derive(Debug)]
+pub struct NegativeCache {
+    capacity: usize,
+    entries: HashMap<CacheKey, NegativeEntry>,
+}
+
+impl NegativeCache {
+    pub fn new(capacity: usize) -> Self {
+        NegativeCache {
+            capacity,
+            entries: HashMap::new(),
+        }
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&mut self, key: &CacheKey) -> Option<(ResponseCode, Answer)> {
+        let now = Instant::now();
+        let entry = self.entries.get(key)?;
+
+        if entry.deadline <= now {
+            self.entries.remove(key);
+            return None;
+        }
+
+        let remaining = (entry.deadline - now).as_secs() as u32;
+        Some((entry.response_code.clone(), entry.soa.with_ttl(remaining)))
+    }
+
+    pub fn insert(&mut self, key: CacheKey, response_code: ResponseCode, soa: &Answer) {
+        let Ok(fields) = soa.record().soa() else {
+            return;
+        };
+        let ttl = soa.ttl().min(fields.minimum);
+
+        if self.capacity == 0 || ttl == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let now = Instant::now();
+            self.entries.retain(|_, entry| entry.deadline > now);
+
+            if self.entries.len() >= self.capacity {
+                let soonest = self.entries
+                                  .iter()
+                                  .min_by_key(|(_, entry)| entry.deadline)
+                                  .map(|(key, _)| key.clone());
+                if let Some(key) = soonest {
+                    self.entries.remove(&key);
+                }
+            }
+        }
+
+        self.entries.insert(key, NegativeEntry {
+            response_code,
+            soa: soa.with_ttl(ttl),
+            deadline: Instant::now() + Duration::from_secs(ttl as u64),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::common::{QClass, RRClass, RRType, Record};
+
+    fn key(name: &str) -> CacheKey {
+        (Name::from(vec![name, "com"]), QType::RRType(RRType::A), QClass::RRClass(RRClass::IN))
+    }
+
+    fn answer(name: &str, ttl: u32) -> Result<Answer> {
+        Ok(Answer::new(&Name::from(vec![name, "com"]), &Record::from_ip_v4("1.2.3.4")?.with_ttl(ttl)))
+    }
+
+    #[test]
+    fn cache_hit_and_miss() -> Result<()> {
+        let mut cache = Cache::new(4);
+        cache.insert(key("a"), vec![answer("a", 300)?]);
+
+        assert_eq!(Some(vec![answer("a", 300)?]), cache.get(&key("a")));
+        assert_eq!(None, cache.get(&key("b")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn zero_ttl_is_not_cached() -> Result<()> {
+        let mut cache = Cache::new(4);
+        cache.insert(key("a"), vec![answer("a", 0)?]);
+
+        assert_eq!(0, cache.len());
+
+        Ok(())
+    }
+
+    fn soa(ttl: u32, minimum: u32) -> Result<Answer> {
+        let record = Record::from_soa(&"ns1.a.com".parse()?, &"admin.a.com".parse()?, 1, 7200, 3600, 1209600, minimum);
+        Ok(Answer::new(&Name::from(vec!["com"]), &record.with_ttl(ttl)))
+    }
+
+    #[test]
+    fn negative_entries_use_soa_minimum() -> Result<()> {
+        let mut cache = NegativeCache::new(1);
+        cache.insert(key("a"), ResponseCode::NameError, &soa(3600, 30)?);
+
+        let (response_code, cached) = cache.get(&key("a")).expect("negative entry missing");
+        assert_eq!(ResponseCode::NameError, response_code);
+        assert!(cached.ttl() <= 30);
+        assert_eq!(None, cache.get(&key("b")));
+
+        cache.insert(key("b"), ResponseCode::NoError, &soa(10, 300)?);
+        assert_eq!(1, cache.len());
+        assert_eq!(Some(ResponseCode::NoError), cache.get(&key("b")).map(|(rcode, _)| rcode));
+
+        cache.insert(key("c"), ResponseCode::NameError, &answer("c", 300)?);
+        cache.insert(key("d"), ResponseCode::NameError, &soa(300, 0)?);
+        assert_eq!(None, cache.get(&key("c")));
+        assert_eq!(None, cache.get(&key("d")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn capacity_is_bounded() -> Result<()> {
+        let mut cache = Cache::new(2);
+        cache.insert(key("a"), vec![answer("a", 100)?]);
+        cache.insert(key("b"), vec![answer("b", 300)?]);
+        cache.insert(key("c"), vec![answer("c", 300)?]);
+
+        assert_eq!(2, cache.len());
+        assert_eq!(None, cache.get(&key("a")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn refresh_is_claimed_once_near_expiry() -> Result<()> {
+        let mut cache = Cache::new(4);
+        cache.insert(key("a"), vec![answer("a", 300)?]);
+
+        assert!(!cache.claim_refresh(&key("a"), 0.1));
+        assert!(!cache.claim_refresh(&key("b"), 1.0));
+        assert!(cache.claim_refresh(&key("a"), 1.0));
+        assert!(!cache.claim_refresh(&key("a"), 1.0));
+
+        cache.insert(key("a"), vec![answer("a", 300)?]);
+        assert!(cache.claim_refresh(&key("a"), 1.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() -> Result<()> {
+        let mut cache = Cache::new(3);
+        cache.insert(key("a"), vec![answer("a", 300)?]);
+        cache.insert(key("b"), vec![answer("b", 600)?]);
+        cache.insert(key("c"), vec![answer("c", 900)?]);
+
+        assert!(cache.get(&key("a")).is_some());
+        cache.insert(key("d"), vec![answer("d", 300)?]);
+
+        assert_eq!(3, cache.len());
+        assert_eq!(None, cache.get(&key("b")));
+        for name in ["a", "c", "d"] {
+            assert!(cache.get(&key(name)).is_some(), "{name} was evicted");
+        }
+
+        Ok(())
+    }
+}
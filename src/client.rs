@@ -0,0 +1,160 @@
+use std::{io::ErrorKind, net::{SocketAddr, UdpSocket}, time::Duration};
+
+use anyhow::Result;
+
+use crate::{
+    common::{Name, QClass, QType, RRClass},
+    message::{Query, Question, Response},
+};
+
+static DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+static DEFAULT_RETRIES: usize = 1;
+
+/// Returned when a server doesn't answer any of the attempts in time.
+#[derive(Debug, thiserror::Error)]
+#[error("No response from {server} after {attempts} attempts")]
+pub struct Timeout {
+    pub server: SocketAddr,
+    pub attempts: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Client {
+    timeout: Duration,
+    retries: usize,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client {
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+}
+
+impl Client {
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn query(&self, server: SocketAddr, name: &Name, qtype: QType) -> Result<Response> {
+        let query = Query::builder()
+            .id(rand::random())
+            .recursion_desired(true)
+            .questions(vec![Question::new(name.clone(), qtype, QClass::RRClass(RRClass::IN))])
+            .build();
+
+        self.exchange(server, &query)
+    }
+
+    pub fn exchange(&self, server: SocketAddr, query: &Query) -> Result<Response> {
+        let socket = UdpSocket::bind(if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+        socket.set_read_timeout(Some(self.timeout))?;
+        let bytes = query.to_vec()?;
+        let mut buf = [0; 4096];
+
+        for _ in 0..=self.retries {
+            socket.send_to(&bytes, server)?;
+
+            loop {
+                let (size, source) = match socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+                    Err(err) => return Err(err.into()),
+                };
+                if source != server {
+                    continue;
+                }
+
+                let Ok(response) = Response::try_from(&buf[..size]) else {
+                    continue;
+                };
+                if response.id() == query.id() && response.questions() == query.questions_ref() {
+                    return Ok(response);
+                }
+            }
+        }
+
+        Err(Timeout { server, attempts: self.retries + 1 }.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{common::{RRType, Record, ResponseCode}, message::Answer, server::ServerBuilder};
+
+    #[test]
+    fn resolve_through_server() -> Result<()> {
+        let mut server = ServerBuilder::default().port(0).build()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+        let address = server.local_addr()?;
+
+        let response = thread::scope(|s| {
+            let handle = s.spawn(|| server.serve());
+            let response = Client::default().query(address, &"codecrafters.io".parse()?, QType::RRType(RRType::A));
+            handle.join().expect("UDP server thread panicked")?;
+            response
+        })?;
+
+        assert_eq!(ResponseCode::NoError, response.response_code());
+        assert_eq!(1, response.answers().len());
+        assert_eq!(Some("8.8.8.8".into()), response.answers()[0].record().as_ipv4());
+
+        Ok(())
+    }
+
+    #[test]
+    fn bogus_replies_are_skipped() -> Result<()> {
+        let server = UdpSocket::bind("127.0.0.1:0")?;
+        let address = server.local_addr()?;
+
+        let responder = thread::spawn(move || -> Result<()> {
+            let mut buf = [0; 512];
+            let (size, source) = server.recv_from(&mut buf)?;
+            let query = Query::try_from(&buf[..size])?;
+            let reply = |name: &Name| -> Result<Vec<u8>> {
+                let question = Question::new(name.clone(), QType::RRType(RRType::A), QClass::RRClass(RRClass::IN));
+                Response::builder()
+                    .id(query.id())
+                    .questions(vec![question])
+                    .answers(vec![Answer::new(name, &Record::from_ip_v4("8.8.8.8")?)])
+                    .build()
+                    .try_into()
+            };
+
+            server.send_to(b"\xff\xff\x81", source)?;
+            server.send_to(&reply(&"example.org".parse()?)?, source)?;
+            server.send_to(&reply(query.questions_ref()[0].name())?, source)?;
+            Ok(())
+        });
+
+        let response = Client::default().query(address, &"codecrafters.io".parse()?, QType::RRType(RRType::A))?;
+        responder.join().expect("responder thread panicked")?;
+
+        assert_eq!(&"codecrafters.io".parse::<Name>()?, response.questions()[0].name());
+        assert_eq!(Some("8.8.8.8".into()), response.answers()[0].record().as_ipv4());
+
+        Ok(())
+    }
+
+    #[test]
+    fn silent_server_times_out() -> Result<()> {
+        let silent = UdpSocket::bind("127.0.0.1:0")?;
+        let client = Client::default().timeout(Duration::from_millis(50));
+
+        let err = client.query(silent.local_addr()?, &"codecrafters.io".parse()?, QType::RRType(RRType::A)).unwrap_err();
+        assert!(err.is::<Timeout>());
+
+        Ok(())
+    }
+}
@@ -1,7 +1,11 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
 use anyhow::{Result, bail};
 
+static MAX_NAME_LENGTH: usize = 255;
+static MAX_POINTER_HOPS: usize = 128;
+static DEFAULT_TTL: u32 = 60;
+
 #[derive(Clone, Debug, Default, PartialEq)]
 #[repr(u8)]
 pub enum OpCode {
@@ -34,8 +38,19 @@ impl From<OpCode> for u8 {
     }
 }
 
+impl Display for OpCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpCode::Query => write!(f, "QUERY"),
+            OpCode::IQuery => write!(f, "IQUERY"),
+            OpCode::Status => write!(f, "STATUS"),
+            OpCode::Reserved(value) => write!(f, "RESERVED{value}"),
+        }
+    }
+}
 
-#[derive(Clone, Debug, PartialEq)]
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum RRType {
     A,
     NS,
@@ -53,6 +68,10 @@ pub enum RRType {
     MINFO,
     MX,
     TXT,
+    AAAA,
+    SRV,
+    OPT,
+    Unknown(u16),
 }
 
 impl TryFrom<u16> for RRType {
@@ -76,7 +95,10 @@ impl TryFrom<u16> for RRType {
             14 => RRType::MINFO,
             15 => RRType::MX,
             16 => RRType::TXT,
-            other => bail!("{other} is not a valid Type"),
+            28 => RRType::AAAA,
+            33 => RRType::SRV,
+            41 => RRType::OPT,
+            other => RRType::Unknown(other),
         })
     }
 }
@@ -100,11 +122,56 @@ impl From<RRType> for u16 {
             RRType::MINFO => 14,
             RRType::MX => 15,
             RRType::TXT => 16,
+            RRType::AAAA => 28,
+            RRType::SRV => 33,
+            RRType::OPT => 41,
+            RRType::Unknown(value) => value,
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+impl FromStr for RRType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_uppercase().as_str() {
+            "A" => RRType::A,
+            "NS" => RRType::NS,
+            "MD" => RRType::MD,
+            "MF" => RRType::MF,
+            "CNAME" => RRType::CNAME,
+            "SOA" => RRType::SOA,
+            "MB" => RRType::MB,
+            "MG" => RRType::MG,
+            "MR" => RRType::MR,
+            "NULL" => RRType::NULL,
+            "WKS" => RRType::WKS,
+            "PTR" => RRType::PTR,
+            "HINFO" => RRType::HINFO,
+            "MINFO" => RRType::MINFO,
+            "MX" => RRType::MX,
+            "TXT" => RRType::TXT,
+            "AAAA" => RRType::AAAA,
+            "SRV" => RRType::SRV,
+            "OPT" => RRType::OPT,
+            other => match other.strip_prefix("TYPE").and_then(|n| n.parse::<u16>().ok()) {
+                Some(value) => RRType::try_from(value)?,
+                None => bail!("Unknown record type {s}"),
+            },
+        })
+    }
+}
+
+impl Display for RRType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RRType::Unknown(value) => write!(f, "TYPE{value}"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum QType {
     RRType(RRType),
     AXFR,
@@ -142,12 +209,51 @@ impl From<QType> for u16 {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+impl FromStr for QType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_uppercase().as_str() {
+            "AXFR" => QType::AXFR,
+            "MAILB" => QType::MAILB,
+            "MAILA" => QType::MAILA,
+            "ANY" => QType::ANY,
+            _ => QType::RRType(s.parse()?),
+        })
+    }
+}
+
+impl Display for QType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QType::RRType(rr) => write!(f, "{rr}"),
+            QType::AXFR => write!(f, "AXFR"),
+            QType::MAILB => write!(f, "MAILB"),
+            QType::MAILA => write!(f, "MAILA"),
+            QType::ANY => write!(f, "ANY"),
+        }
+    }
+}
+
+impl QType {
+    pub fn matches(&self, rrtype: &RRType) -> bool {
+        match self {
+            QType::RRType(expected) => expected == rrtype,
+            QType::MAILB => matches!(rrtype, RRType::MB | RRType::MG | RRType::MR),
+            QType::MAILA => matches!(rrtype, RRType::MD | RRType::MF),
+            QType::ANY => true,
+            QType::AXFR => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum RRClass {
     IN,
     CS,
     CH,
     HS,
+    Unknown(u16),
 }
 
 impl From<RRClass> for u16 {
@@ -157,6 +263,33 @@ impl From<RRClass> for u16 {
             RRClass::CS => 2,
             RRClass::CH => 3,
             RRClass::HS => 4,
+            RRClass::Unknown(value) => value,
+        }
+    }
+}
+
+impl FromStr for RRClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_uppercase().as_str() {
+            "IN" => RRClass::IN,
+            "CS" => RRClass::CS,
+            "CH" => RRClass::CH,
+            "HS" => RRClass::HS,
+            other => match other.strip_prefix("CLASS").and_then(|n| n.parse::<u16>().ok()) {
+                Some(value) => RRClass::try_from(value)?,
+                None => bail!("Unknown record class {s}"),
+            },
+        })
+    }
+}
+
+impl Display for RRClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RRClass::Unknown(value) => write!(f, "CLASS{value}"),
+            other => write!(f, "{other:?}"),
         }
     }
 }
@@ -170,12 +303,12 @@ impl TryFrom<u16> for RRClass {
             2 => RRClass::CS,
             3 => RRClass::CH,
             4 => RRClass::HS,
-            other => bail!("{other} is not a valid Class")
+            other => RRClass::Unknown(other),
         })
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum QClass {
     RRClass(RRClass),
     ANY,
@@ -204,6 +337,26 @@ impl From<QClass> for u16 {
     }
 }
 
+impl FromStr for QClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "ANY" => Ok(QClass::ANY),
+            _ => Ok(QClass::RRClass(s.parse()?)),
+        }
+    }
+}
+
+impl Display for QClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QClass::RRClass(rr) => write!(f, "{rr}"),
+            QClass::ANY => write!(f, "ANY"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Name {
     labels: Vec<String>,
@@ -228,6 +381,35 @@ impl From<Vec<&str>> for Name {
     }
 }
 
+impl Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for label in &self.labels {
+            write!(f, "{label}.")?;
+        }
+
+        match self.pointer {
+            Some(ptr) if self.labels.is_empty() => write!(f, "-> @{ptr}"),
+            Some(ptr) => write!(f, " -> @{ptr}"),
+            None if self.labels.is_empty() => write!(f, "."),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Name {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "." {
+            return Ok(Name::from(Vec::<String>::new()));
+        }
+
+        let labels = s.strip_suffix('.').unwrap_or(s).split('.').map(String::from).collect();
+
+        Name::try_new(labels)
+    }
+}
+
 impl TryFrom<&[u8]> for Name {
     type Error = anyhow::Error;
 
@@ -236,17 +418,18 @@ impl TryFrom<&[u8]> for Name {
         let mut last_pos = 0;
         let mut labels = vec![];
 
-        while last_pos < length {
+        while let Some(marker_byte) = value.get(last_pos) {
             let marker = last_pos;
-            match value[marker..].first().unwrap() {
+            match marker_byte {
                 0 => { return Ok(Name::from(labels)) },
                 &upper_byte if upper_byte >= 0xc0 => {
                     if let Some(&lower_byte) = value.get(marker + 1) {
                         return Ok(Name {
                             labels,
-                            pointer: Some(u16::from_be_bytes([upper_byte & 0x03, lower_byte])),
+                            pointer: Some(u16::from_be_bytes([upper_byte & 0x3f, lower_byte])),
                         })
                     }
+                    bail!("Corrupt name: truncated compression pointer")
                 },
                 &label_length if label_length < 64 => {
                     let start = marker + 1;
@@ -254,6 +437,9 @@ impl TryFrom<&[u8]> for Name {
                     if end >= length {
                         bail!("Corrupt name: truncated label")
                     }
+                    if end + 1 > MAX_NAME_LENGTH {
+                        bail!("Corrupt name: exceeds 255 octets")
+                    }
 
                     let label = String::from_utf8(value[start..end].to_vec())?;
                     labels.push(label);
@@ -268,10 +454,63 @@ impl TryFrom<&[u8]> for Name {
 }
 
 impl Name {
+    pub fn from_message(message: &[u8], offset: usize) -> Result<(Name, usize)> {
+        let mut labels = vec![];
+        let mut pos = offset;
+        let mut end = None;
+        let mut length = 1;
+
+        loop {
+            let Some(&marker) = message.get(pos) else {
+                bail!("Corrupt name: no null label terminator")
+            };
+
+            match marker {
+                0 => break,
+                upper_byte if upper_byte >= 0xc0 => {
+                    let Some(&lower_byte) = message.get(pos + 1) else {
+                        bail!("Corrupt name: truncated compression pointer")
+                    };
+                    let target = u16::from_be_bytes([upper_byte & 0x3f, lower_byte]) as usize;
+                    if target >= pos {
+                        bail!("Corrupt name: compression pointer at {pos} doesn't point backwards")
+                    }
+                    end.get_or_insert(pos + 2);
+                    pos = target;
+                },
+                label_length if label_length < 64 => {
+                    let start = pos + 1;
+                    let stop = start + label_length as usize;
+                    if stop >= message.len() {
+                        bail!("Corrupt name: truncated label")
+                    }
+
+                    length += label_length as usize + 1;
+                    if length > MAX_NAME_LENGTH {
+                        bail!("Corrupt name: exceeds 255 octets")
+                    }
+
+                    labels.push(String::from_utf8(message[start..stop].to_vec())?);
+                    pos = stop;
+                },
+                other => bail!("Corrupt name: label length {other} is illegal"),
+            }
+        }
+
+        Ok((Name::from(labels), end.unwrap_or(pos + 1)))
+    }
+
     pub fn new(labels: Vec<String>, pointer: Option<u16>) -> Self {
         Name { labels, pointer }
     }
 
+    pub fn try_new(labels: Vec<String>) -> Result<Self> {
+        let name = Name::from(labels);
+        name.validate()?;
+
+        Ok(name)
+    }
+
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         let labels_len = self.labels.iter().map(|l| l.len() + 1).sum::<usize>();
@@ -286,25 +525,71 @@ impl Name {
     pub fn pointer(&self) -> &Option<u16> {
         &self.pointer
     }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, String> {
+        self.labels.iter()
+    }
+
+    pub fn parent(&self) -> Option<Name> {
+        let (_, rest) = self.labels.split_first()?;
+
+        Some(Name {
+            labels: rest.to_vec(),
+            pointer: self.pointer,
+        })
+    }
+
+    pub fn is_subdomain_of(&self, other: &Name) -> bool {
+        self.labels.len() >= other.labels.len()
+            && self.labels.iter().rev().zip(other.labels.iter().rev()).all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+
+    pub fn to_lowercase(&self) -> Name {
+        Name {
+            labels: self.labels.iter().map(|l| l.to_ascii_lowercase()).collect(),
+            pointer: self.pointer,
+        }
+    }
     
-    pub fn expand(&self, references: &HashMap<u16, Vec<String>>) -> Result<Name> {
-        let expanded = if let Some(ptr) = self.pointer {
-            if let Some(suffix) = references.get(&ptr) {
-                suffix.clone()
-            } else {
+    pub fn expand(&self, references: &HashMap<u16, Name>) -> Result<Name> {
+        let mut labels = self.labels.clone();
+        let mut pointer = self.pointer;
+        let mut hops = 0;
+
+        while let Some(ptr) = pointer {
+            hops += 1;
+            if hops > MAX_POINTER_HOPS {
+                bail!("Corrupt name: compression loop")
+            }
+
+            let Some(suffix) = references.get(&ptr) else {
                 bail!("Corrupt name: compression pointing to non-registered name at {ptr}")
+            };
+            labels.extend(suffix.labels.iter().cloned());
+            pointer = suffix.pointer;
+        }
+
+        let name = Name::from(labels);
+        name.validate()?;
+
+        Ok(name)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for label in &self.labels {
+            if label.is_empty() {
+                bail!("Invalid name: empty label")
             }
-        } else {
-            vec![]
-        };
+            if label.len() > 63 {
+                bail!("Invalid name: label {label:?} is longer than 63 bytes")
+            }
+        }
 
-        Ok(Name {
-            labels: [ self.labels.clone(), expanded ].iter()
-                                                     .flatten()
-                                                     .cloned()
-                                                     .collect::<Vec<_>>(),
-            pointer: None,
-        })
+        if self.len() > MAX_NAME_LENGTH {
+            bail!("Corrupt name: exceeds 255 octets")
+        }
+
+        Ok(())
     }
 
     pub fn compress(&self, references: &HashMap<Vec<String>, u16>) -> Result<Name> {
@@ -343,39 +628,349 @@ impl Name {
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub enum ResponseCode {
-    NoError = 0,
+    NoError,
     #[default]
     FormatError,
     ServerFailure,
     NameError,
     NotImplemented,
     Refused,
-    Reserved,
+    Reserved(u8),
+    BadVersion,
+}
+
+impl TryFrom<u8> for ResponseCode {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        Ok(match value {
+            0 => ResponseCode::NoError,
+            1 => ResponseCode::FormatError,
+            2 => ResponseCode::ServerFailure,
+            3 => ResponseCode::NameError,
+            4 => ResponseCode::NotImplemented,
+            5 => ResponseCode::Refused,
+            6..=15 => ResponseCode::Reserved(value),
+            16 => ResponseCode::BadVersion,
+            other => bail!("{other} is not a valid RCode"),
+        })
+    }
+}
+
+impl From<ResponseCode> for u8 {
+    fn from(value: ResponseCode) -> Self {
+        match value {
+            ResponseCode::NoError => 0,
+            ResponseCode::FormatError => 1,
+            ResponseCode::ServerFailure => 2,
+            ResponseCode::NameError => 3,
+            ResponseCode::NotImplemented => 4,
+            ResponseCode::Refused => 5,
+            ResponseCode::Reserved(value) => value,
+            ResponseCode::BadVersion => 16,
+        }
+    }
+}
+
+impl Display for ResponseCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResponseCode::NoError => write!(f, "NOERROR"),
+            ResponseCode::FormatError => write!(f, "FORMERR"),
+            ResponseCode::ServerFailure => write!(f, "SERVFAIL"),
+            ResponseCode::NameError => write!(f, "NXDOMAIN"),
+            ResponseCode::NotImplemented => write!(f, "NOTIMP"),
+            ResponseCode::Refused => write!(f, "REFUSED"),
+            ResponseCode::BadVersion => write!(f, "BADVERS"),
+            ResponseCode::Reserved(value) => write!(f, "RESERVED{value}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Soa {
+    pub mname: Name,
+    pub rname: Name,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Record {
     rrtype: RRType,
     rrclass: RRClass,
+    ttl: u32,
     data: Vec<u8>,
 }
 
 impl Record {
+    pub fn new(rrtype: RRType, rrclass: RRClass, data: Vec<u8>) -> Self {
+        Record { rrtype, rrclass, ttl: DEFAULT_TTL, data }
+    }
+
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub(crate) fn with_data(&self, data: Vec<u8>) -> Self {
+        Record { data, ..self.clone() }
+    }
+
+    pub fn with_class(mut self, rrclass: RRClass) -> Self {
+        self.rrclass = rrclass;
+        self
+    }
+
     pub fn from_ip_v4(source: &str) -> Result<Self> {
-        let components: std::result::Result<Vec<_>, _> =
-            source.split('.')
-                  .map(|c| c.parse::<u8>())
-                  .collect();
+        let address: std::net::Ipv4Addr = source.parse()?;
 
-        Ok(Record {
-            rrtype: RRType::A,
-            rrclass: RRClass::IN,
-            data: components?,
+        Ok(Record::new(RRType::A, RRClass::IN, address.octets().to_vec()))
+    }
+
+    pub fn from_ip_v6(source: &str) -> Result<Self> {
+        let address: std::net::Ipv6Addr = source.parse()?;
+
+        Ok(Record::new(RRType::AAAA, RRClass::IN, address.octets().to_vec()))
+    }
+
+    pub fn as_ipv4(&self) -> Option<String> {
+        match (&self.rrtype, &self.data[..]) {
+            (RRType::A, [a, b, c, d]) => Some(format!("{a}.{b}.{c}.{d}")),
+            _ => None,
+        }
+    }
+
+    pub fn from_soa(mname: &Name, rname: &Name, serial: u32, refresh: u32, retry: u32, expire: u32, minimum: u32) -> Self {
+        let mut data = mname.to_vec();
+        data.extend(rname.to_vec());
+        for value in [serial, refresh, retry, expire, minimum] {
+            data.extend(value.to_be_bytes());
+        }
+
+        Record::new(RRType::SOA, RRClass::IN, data)
+    }
+
+    pub fn soa(&self) -> Result<Soa> {
+        if self.rrtype != RRType::SOA {
+            bail!("Not an SOA record");
+        }
+
+        let (mut names, end) = self.names_at(0, 2)?;
+        let Ok::<[u8; 20], _>(fields) = self.data[end..].try_into() else {
+            bail!("Corrupt record: SOA data has the wrong length")
+        };
+        let field = |k: usize| u32::from_be_bytes([fields[k * 4], fields[k * 4 + 1], fields[k * 4 + 2], fields[k * 4 + 3]]);
+        let rname = names.pop().unwrap();
+        let mname = names.pop().unwrap();
+
+        Ok(Soa {
+            mname,
+            rname,
+            serial: field(0),
+            refresh: field(1),
+            retry: field(2),
+            expire: field(3),
+            minimum: field(4),
         })
     }
 
+    pub fn from_mx(preference: u16, exchange: &Name) -> Self {
+        let mut data = preference.to_be_bytes().to_vec();
+        data.extend(exchange.to_vec());
+
+        Record::new(RRType::MX, RRClass::IN, data)
+    }
+
+    pub fn mx_preference(&self) -> Result<u16> {
+        match (&self.rrtype, &self.data[..]) {
+            (RRType::MX, [upper, lower, ..]) => Ok(u16::from_be_bytes([*upper, *lower])),
+            (RRType::MX, _) => bail!("Corrupt record: MX data is too short"),
+            _ => bail!("Not an MX record"),
+        }
+    }
+
+    pub fn mx_exchange(&self) -> Result<Name> {
+        if self.rrtype != RRType::MX {
+            bail!("Not an MX record");
+        }
+
+        let (mut names, _) = self.names_at(2, 1)?;
+        Ok(names.pop().unwrap())
+    }
+
+    pub fn from_cname(target: &Name) -> Self {
+        Record::new(RRType::CNAME, RRClass::IN, target.to_vec())
+    }
+
+    pub fn cname_target(&self) -> Result<Name> {
+        self.single_name(RRType::CNAME)
+    }
+
+    pub fn from_ns(nameserver: &Name) -> Self {
+        Record::new(RRType::NS, RRClass::IN, nameserver.to_vec())
+    }
+
+    pub fn ns_name(&self) -> Result<Name> {
+        self.single_name(RRType::NS)
+    }
+
+    pub fn from_ptr(name: &Name) -> Self {
+        Record::new(RRType::PTR, RRClass::IN, name.to_vec())
+    }
+
+    pub fn ptr_name(&self) -> Result<Name> {
+        self.single_name(RRType::PTR)
+    }
+
+    pub fn from_mb(mailbox: &Name) -> Self {
+        Record::new(RRType::MB, RRClass::IN, mailbox.to_vec())
+    }
+
+    pub fn mb_name(&self) -> Result<Name> {
+        self.single_name(RRType::MB)
+    }
+
+    pub fn from_mg(member: &Name) -> Self {
+        Record::new(RRType::MG, RRClass::IN, member.to_vec())
+    }
+
+    pub fn mg_name(&self) -> Result<Name> {
+        self.single_name(RRType::MG)
+    }
+
+    pub fn from_mr(mailbox: &Name) -> Self {
+        Record::new(RRType::MR, RRClass::IN, mailbox.to_vec())
+    }
+
+    pub fn mr_name(&self) -> Result<Name> {
+        self.single_name(RRType::MR)
+    }
+
+    pub fn from_srv(priority: u16, weight: u16, port: u16, target: &Name) -> Self {
+        let mut data = vec![];
+        for value in [priority, weight, port] {
+            data.extend(value.to_be_bytes());
+        }
+        data.extend(target.to_vec());
+
+        Record::new(RRType::SRV, RRClass::IN, data)
+    }
+
+    fn srv_field(&self, k: usize) -> Result<u16> {
+        if self.rrtype != RRType::SRV {
+            bail!("Not an SRV record");
+        }
+
+        match self.data.get(k * 2..k * 2 + 2) {
+            Some(&[upper, lower]) => Ok(u16::from_be_bytes([upper, lower])),
+            _ => bail!("Corrupt record: SRV data is too short"),
+        }
+    }
+
+    pub fn srv_priority(&self) -> Result<u16> { self.srv_field(0) }
+    pub fn srv_weight(&self) -> Result<u16> { self.srv_field(1) }
+    pub fn srv_port(&self) -> Result<u16> { self.srv_field(2) }
+
+    pub fn srv_target(&self) -> Result<Name> {
+        if self.rrtype != RRType::SRV {
+            bail!("Not an SRV record");
+        }
+
+        let (mut names, _) = self.names_at(6, 1)?;
+        Ok(names.pop().unwrap())
+    }
+
+    pub fn from_txt(strings: &[&str]) -> Result<Self> {
+        let mut data = vec![];
+        for string in strings {
+            if string.len() > 255 {
+                bail!("TXT string longer than 255 bytes: {}", string.len());
+            }
+
+            data.push(string.len() as u8);
+            data.extend(string.as_bytes());
+        }
+
+        Ok(Record::new(RRType::TXT, RRClass::IN, data))
+    }
+
+    pub fn txt_strings(&self) -> Result<Vec<String>> {
+        if self.rrtype != RRType::TXT {
+            bail!("Not a TXT record");
+        }
+
+        let mut strings = vec![];
+        let mut rest = &self.data[..];
+        while let Some((&length, tail)) = rest.split_first() {
+            let Some(string) = tail.get(..length as usize) else {
+                bail!("Corrupt record: truncated TXT string")
+            };
+
+            strings.push(String::from_utf8(string.to_vec())?);
+            rest = &tail[length as usize..];
+        }
+
+        Ok(strings)
+    }
+
+    fn single_name(&self, rrtype: RRType) -> Result<Name> {
+        if self.rrtype != rrtype {
+            bail!("Not a {rrtype:?} record");
+        }
+
+        let (mut names, end) = self.names_at(0, 1)?;
+        if end != self.data.len() {
+            bail!("Corrupt record: trailing bytes after {rrtype:?} name");
+        }
+
+        Ok(names.pop().unwrap())
+    }
+
+    pub(crate) fn name_layout(&self) -> Option<(usize, usize)> {
+        match self.rrtype {
+            RRType::NS | RRType::MD | RRType::MF | RRType::CNAME |
+            RRType::MB | RRType::MG | RRType::MR | RRType::PTR => Some((0, 1)),
+            RRType::MX => Some((2, 1)),
+            RRType::SRV => Some((6, 1)),
+            RRType::SOA => Some((0, 2)),
+            _ => None,
+        }
+    }
+
+    fn names_at(&self, offset: usize, count: usize) -> Result<(Vec<Name>, usize)> {
+        let mut names = vec![];
+        let mut pos = offset;
+
+        for _ in 0..count {
+            let Some(rest) = self.data.get(pos..) else {
+                bail!("Corrupt record: truncated RDATA")
+            };
+            let name = Name::try_from(rest)?;
+            if name.pointer.is_some() {
+                bail!("Corrupt record: compressed name in RDATA")
+            }
+
+            pos += name.len();
+            names.push(name);
+        }
+
+        Ok((names, pos))
+    }
+
+    pub fn embedded_names(&self) -> Option<(usize, Vec<Name>, usize)> {
+        let (offset, count) = self.name_layout()?;
+        let (names, end) = self.names_at(offset, count).ok()?;
+
+        Some((offset, names, end))
+    }
+
     pub fn rrtype(&self) -> &RRType { &self.rrtype }
     pub fn rrclass(&self) -> &RRClass { &self.rrclass }
+    pub fn ttl(&self) -> u32 { self.ttl }
     pub fn data(&self) -> &Vec<u8> { &self.data }
 
     pub fn to_vec(&self) -> Vec<u8> {
@@ -388,6 +983,70 @@ impl Record {
 
         data.iter().flatten().copied().collect()
     }
+
+    fn rdata(&self) -> Option<String> {
+        Some(match self.rrtype {
+            RRType::A => self.as_ipv4()?,
+            RRType::AAAA => std::net::Ipv6Addr::from(<[u8; 16]>::try_from(&self.data[..]).ok()?).to_string(),
+            RRType::NS => self.ns_name().ok()?.to_string(),
+            RRType::CNAME => self.cname_target().ok()?.to_string(),
+            RRType::PTR => self.ptr_name().ok()?.to_string(),
+            RRType::MB => self.mb_name().ok()?.to_string(),
+            RRType::MG => self.mg_name().ok()?.to_string(),
+            RRType::MR => self.mr_name().ok()?.to_string(),
+            RRType::MX => format!("{} {}", self.mx_preference().ok()?, self.mx_exchange().ok()?),
+            RRType::SRV => format!("{} {} {} {}",
+                                   self.srv_priority().ok()?, self.srv_weight().ok()?,
+                                   self.srv_port().ok()?, self.srv_target().ok()?),
+            RRType::SOA => {
+                let soa = self.soa().ok()?;
+                format!("{} {} {} {} {} {} {}",
+                        soa.mname, soa.rname, soa.serial, soa.refresh, soa.retry, soa.expire, soa.minimum)
+            },
+            RRType::TXT => self.txt_strings().ok()?
+                               .iter()
+                               .map(|s| quote(s))
+                               .collect::<Vec<_>>()
+                               .join(" "),
+            _ => return None,
+        })
+    }
+}
+
+/// Quotes a character string the way master files expect it: `"` and `\` are
+/// escaped with a backslash and control characters are written as `\DDD`.
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            },
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\{:03}", c as u8)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\t{}\t{}\t", self.ttl, self.rrclass, self.rrtype)?;
+
+        match self.rdata() {
+            Some(rdata) => write!(f, "{rdata}"),
+            None => {
+                write!(f, "\\# {}", self.data.len())?;
+                if !self.data.is_empty() {
+                    write!(f, " ")?;
+                }
+                self.data.iter().try_for_each(|b| write!(f, "{b:02x}"))
+            },
+        }
+    }
 }
 
 #[cfg(test)]
@@ -406,8 +1065,8 @@ mod tests {
             pointer: Some(12),
         }
     });
-    static REFERENCES:     Lazy<HashMap::<u16, Vec<String>>> = Lazy::new(|| {
-        [ (12, vec![String::from("server"), String::from("com")]) ]
+    static REFERENCES:     Lazy<HashMap::<u16, Name>> = Lazy::new(|| {
+        [ (12, Name::from(vec!["server", "com"])) ]
             .into_iter()
             .collect()
     });
@@ -422,6 +1081,7 @@ mod tests {
         Record {
             rrtype: RRType::A,
             rrclass: RRClass::IN,
+            ttl: 60,
             data: b"\x01\x02\x03\x04".to_vec()
         }
     });
@@ -468,6 +1128,38 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn decode_name_from_message() -> Result<()> {
+        let mut message = vec![0; 12];
+        message.extend(ENCODED_LABELS.as_bytes());
+        message.extend(b"\x04mail\xc0\x10\xc0\x24");
+
+        assert_eq!((Name::from(LABELS.to_vec()), 28), Name::from_message(&message, 12)?);
+        assert_eq!((Name::from(vec!["mail", "server", "com"]), 35), Name::from_message(&message, 28)?);
+        assert!(Name::from_message(&message, 35).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn name_navigation() -> Result<()> {
+        let name: Name = "codecrafters.io".parse()?;
+        let zone: Name = "Example.COM".parse()?;
+
+        assert_eq!(vec!["codecrafters", "io"], name.iter().collect::<Vec<_>>());
+        assert_eq!(Some("io".parse()?), name.parent());
+        assert_eq!(Some(Name::from(Vec::<String>::new())), name.parent().and_then(|n| n.parent()));
+        assert_eq!(None, Name::from(Vec::<String>::new()).parent());
+
+        assert!("www.example.com".parse::<Name>()?.is_subdomain_of(&zone));
+        assert!("example.com".parse::<Name>()?.is_subdomain_of(&zone));
+        assert!(!"badexample.com".parse::<Name>()?.is_subdomain_of(&zone));
+        assert!(!"com".parse::<Name>()?.is_subdomain_of(&zone));
+        assert!(!"www.example.org".parse::<Name>()?.is_subdomain_of(&zone));
+
+        Ok(())
+    }
+
     #[test]
     fn expand_name_with_pointer() -> Result<()> {
         assert_eq!(LABELS, LABELS_AND_POINTER.expand(&REFERENCES)?.labels());
@@ -475,6 +1167,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn expand_chained_pointers() -> Result<()> {
+        let references: HashMap<u16, Name> = [
+            (12, Name::new(vec![String::from("server")], Some(30))),
+            (30, Name::from(vec!["com"])),
+        ].into_iter().collect();
+
+        assert_eq!(LABELS, LABELS_AND_POINTER.expand(&references)?.labels());
+
+        Ok(())
+    }
+
+    #[test]
+    fn expand_pointer_loop() {
+        let references: HashMap<u16, Name> = [
+            (12, Name::new(vec![], Some(14))),
+            (14, Name::new(vec![], Some(12))),
+        ].into_iter().collect();
+
+        let err = LABELS_AND_POINTER.expand(&references).unwrap_err();
+        assert_eq!("Corrupt name: compression loop", err.to_string());
+    }
+
     #[test]
     fn compress_name() -> Result<()> {
         let name = Name::from(LABELS.to_vec());
@@ -483,15 +1198,322 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn unknown_type_round_trip() -> Result<()> {
+        assert_eq!(RRType::Unknown(99), RRType::try_from(99)?);
+        assert_eq!(99, u16::from(RRType::Unknown(99)));
+        assert_eq!(QType::RRType(RRType::Unknown(99)), QType::try_from(99)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rrtype_mnemonic_round_trip() -> Result<()> {
+        let all = [
+            RRType::A, RRType::NS, RRType::MD, RRType::MF, RRType::CNAME, RRType::SOA, RRType::MB,
+            RRType::MG, RRType::MR, RRType::NULL, RRType::WKS, RRType::PTR, RRType::HINFO,
+            RRType::MINFO, RRType::MX, RRType::TXT, RRType::AAAA, RRType::SRV, RRType::OPT,
+            RRType::Unknown(65280),
+        ];
+
+        for rrtype in all {
+            assert_eq!(rrtype, rrtype.to_string().parse()?);
+            assert_eq!(rrtype, rrtype.to_string().to_lowercase().parse()?);
+        }
+
+        assert_eq!("CNAME", RRType::CNAME.to_string());
+        assert_eq!(RRType::MX, "TYPE15".parse()?);
+        assert!("BOGUS".parse::<RRType>().is_err());
+        assert!("TYPE".parse::<RRType>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn qtype_and_qclass_mnemonics() -> Result<()> {
+        assert_eq!(QType::AXFR, "AXFR".parse::<QType>()?);
+        assert_eq!(QType::ANY, "any".parse::<QType>()?);
+        assert_eq!(QType::RRType(RRType::AAAA), "AAAA".parse::<QType>()?);
+        assert_eq!(QClass::RRClass(RRClass::IN), "IN".parse::<QClass>()?);
+        assert_eq!(QClass::RRClass(RRClass::CH), "ch".parse::<QClass>()?);
+        assert_eq!(QClass::ANY, "ANY".parse::<QClass>()?);
+        assert!("IXFR".parse::<QType>().is_err());
+        assert!("NOWHERE".parse::<QClass>().is_err());
+
+        for qtype in [QType::AXFR, QType::MAILB, QType::MAILA, QType::ANY, QType::RRType(RRType::MX), QType::RRType(RRType::Unknown(999))] {
+            assert_eq!(qtype, qtype.to_string().parse()?);
+        }
+        for qclass in [QClass::ANY, QClass::RRClass(RRClass::IN), QClass::RRClass(RRClass::CS),
+                       QClass::RRClass(RRClass::CH), QClass::RRClass(RRClass::HS), QClass::RRClass(RRClass::Unknown(254))] {
+            assert_eq!(qclass, qclass.to_string().parse()?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_class_round_trip() -> Result<()> {
+        assert_eq!(RRClass::Unknown(4096), RRClass::try_from(4096)?);
+        assert_eq!(4096, u16::from(RRClass::Unknown(4096)));
+        assert_eq!(QClass::ANY, QClass::try_from(255)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn qtype_matches_rrtype() {
+        assert!(QType::RRType(RRType::A).matches(&RRType::A));
+        assert!(!QType::RRType(RRType::AAAA).matches(&RRType::A));
+        assert!(QType::ANY.matches(&RRType::TXT));
+        assert!(QType::MAILB.matches(&RRType::MG));
+        assert!(!QType::AXFR.matches(&RRType::A));
+    }
+
+    #[test]
+    fn parse_name() -> Result<()> {
+        let target = Name::from(LABELS.to_vec());
+
+        assert_eq!(target, "www.server.com".parse()?);
+        assert_eq!(target, "www.server.com.".parse()?);
+        assert_eq!(Name::from(Vec::<String>::new()), ".".parse()?);
+
+        assert!("a..b".parse::<Name>().is_err());
+        assert!("".parse::<Name>().is_err());
+        assert!("a.b..".parse::<Name>().is_err());
+        assert!(format!("{}.com", "x".repeat(64)).parse::<Name>().is_err());
+
+        Ok(())
+    }
+
+    fn long_name(last_label: usize) -> Name {
+        let mut labels = vec!["x".repeat(63); 3];
+        labels.push("y".repeat(last_label));
+        Name::from(labels)
+    }
+
+    #[test]
+    fn name_length_limit() -> Result<()> {
+        let longest = long_name(61);
+        assert_eq!(255, longest.len());
+        longest.validate()?;
+        assert_eq!(longest, Name::try_from(&longest.to_vec()[..])?);
+        assert_eq!(longest, longest.to_string().parse()?);
+
+        let too_long = long_name(62);
+        assert!(too_long.validate().is_err());
+        assert!(Name::try_from(&too_long.to_vec()[..]).is_err());
+        assert!(too_long.to_string().parse::<Name>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn label_length_limit() {
+        assert!(Name::try_new(vec!["x".repeat(63), "com".into()]).is_ok());
+        assert!(Name::try_new(vec!["x".repeat(64), "com".into()]).is_err());
+        assert!(Name::try_new(vec!["".into(), "com".into()]).is_err());
+    }
+
+    #[test]
+    fn display_name() {
+        assert_eq!("codecrafters.io.", Name::from(vec!["codecrafters", "io"]).to_string());
+        assert_eq!(".", Name::from(Vec::<String>::new()).to_string());
+        assert_eq!("www. -> @12", LABELS_AND_POINTER.to_string());
+    }
+
+    #[test]
+    fn lowercase_name() {
+        let name = Name::from(vec!["CodeCrafters", "IO"]);
+        assert_eq!(Name::from(vec!["codecrafters", "io"]), name.to_lowercase());
+    }
+
+    #[test]
+    fn response_code_round_trip() -> Result<()> {
+        for code in 0..=16u8 {
+            assert_eq!(code, u8::from(ResponseCode::try_from(code)?));
+        }
+
+        assert_eq!(ResponseCode::Refused, ResponseCode::try_from(5)?);
+        assert_eq!(ResponseCode::Reserved(6), ResponseCode::try_from(6)?);
+        assert_eq!(ResponseCode::BadVersion, ResponseCode::try_from(16)?);
+        assert!(ResponseCode::try_from(17).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn ipv4_str_to_record() -> Result<()> {
         assert_eq!(IPV4_RECORD.clone(), Record::from_ip_v4(IPV4)?);
         Ok(())
     }
 
+    #[test]
+    fn record_to_ipv4_str() -> Result<()> {
+        assert_eq!(Some(IPV4.into()), Record::from_ip_v4(IPV4)?.as_ipv4());
+
+        let truncated = Record { data: vec![1, 2, 3], ..IPV4_RECORD.clone() };
+        assert_eq!(None, truncated.as_ipv4());
+
+        Ok(())
+    }
+
+    #[test]
+    fn display_record() -> Result<()> {
+        let target: Name = "mail.example.com".parse()?;
+
+        assert_eq!("60\tIN\tA\t1.2.3.4", IPV4_RECORD.to_string());
+        assert_eq!("60\tIN\tAAAA\t2001:db8::1", Record::from_ip_v6("2001:db8::1")?.to_string());
+        assert_eq!("300\tIN\tMX\t10 mail.example.com.", Record::from_mx(10, &target).with_ttl(300).to_string());
+        assert_eq!("60\tIN\tSRV\t1 2 25 mail.example.com.", Record::from_srv(1, 2, 25, &target).to_string());
+        assert_eq!("60\tIN\tTXT\t\"a b\" \"c\"", Record::from_txt(&["a b", "c"])?.to_string());
+        assert_eq!("60\tIN\tTXT\t\"say \\\"hi\\\" \\\\ \\009\"", Record::from_txt(&["say \"hi\" \\ \t"])?.to_string());
+        assert_eq!("60\tCLASS99\tTYPE99\t\\# 2 beef", Record::new(RRType::Unknown(99), RRClass::Unknown(99), vec![0xbe, 0xef]).to_string());
+        assert_eq!("60\tIN\tA\t\\# 3 010203", Record { data: vec![1, 2, 3], ..IPV4_RECORD.clone() }.to_string());
+
+        Ok(())
+    }
+
     #[test]
     fn encode_record() -> Result<()> {
         assert_eq!(ENCODED_IPV4_RECORD.clone(), IPV4_RECORD.to_vec());
         Ok(())
     }
+
+    #[test]
+    fn ipv6_record() -> Result<()> {
+        let record = Record::from_ip_v6("2001:db8::1")?;
+
+        assert_eq!(RRType::AAAA, *record.rrtype());
+        assert_eq!(b"\x20\x01\x0d\xb8\0\0\0\0\0\0\0\0\0\0\0\x01", &record.data()[..]);
+        assert!(Record::from_ip_v6("1.2.3.4").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn ipv4_record_needs_four_octets() {
+        assert!(Record::from_ip_v4("1.2.3").is_err());
+        assert!(Record::from_ip_v4("1.2.3.4.5").is_err());
+        assert!(Record::from_ip_v4("::1").is_err());
+    }
+
+    #[test]
+    fn generic_record() {
+        let record = Record::new(RRType::Unknown(65280), RRClass::CH, vec![0xde, 0xad, 0xbe, 0xef]);
+
+        assert_eq!(b"\xff\x00\x00\x03\x00\x04\xde\xad\xbe\xef", &record.to_vec()[..]);
+    }
+
+    #[test]
+    fn soa_record() -> Result<()> {
+        let mname: Name = "ns1.example.com".parse()?;
+        let rname: Name = "admin.example.com".parse()?;
+        let record = Record::from_soa(&mname, &rname, 2024010101, 7200, 3600, 1209600, 300);
+        let encoded = b"\x00\x06\x00\x01\x00\x38\
+                        \x03ns1\x07example\x03com\x00\x05admin\x07example\x03com\x00\
+                        \x78\xa3\xf1\x75\x00\x00\x1c\x20\x00\x00\x0e\x10\x00\x12\x75\x00\x00\x00\x01\x2c";
+
+        assert_eq!(&encoded[..], &record.to_vec()[..]);
+        assert_eq!(Soa {
+            mname,
+            rname,
+            serial: 2024010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+        }, record.soa()?);
+        assert!(Record::from_ip_v4("1.2.3.4")?.soa().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn mx_record() -> Result<()> {
+        let exchange: Name = "mail.codecrafters.io".parse()?;
+        let record = Record::from_mx(10, &exchange);
+        let encoded = b"\x00\x0f\x00\x01\x00\x18\x00\x0a\x04mail\x0ccodecrafters\x02io\x00";
+
+        assert_eq!(&encoded[..], &record.to_vec()[..]);
+        assert_eq!(10, record.mx_preference()?);
+        assert_eq!(exchange, record.mx_exchange()?);
+        assert!(Record::from_ip_v4("1.2.3.4")?.mx_exchange().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn cname_record() -> Result<()> {
+        let target: Name = "www.codecrafters.io".parse()?;
+        let record = Record::from_cname(&target);
+        let encoded = record.to_vec();
+
+        assert_eq!(b"\x00\x05\x00\x01\x00\x15", &encoded[..6]);
+        assert_eq!(target, Name::try_from(&encoded[6..])?);
+        assert_eq!(target, record.cname_target()?);
+        assert!(Record::from_mx(10, &target).cname_target().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn ns_record() -> Result<()> {
+        let nameserver: Name = "ns1.example.com".parse()?;
+        let record = Record::from_ns(&nameserver);
+
+        assert_eq!(b"\x00\x02\x00\x01\x00\x11\x03ns1\x07example\x03com\x00", &record.to_vec()[..]);
+        assert_eq!(nameserver, record.ns_name()?);
+        assert!(Record::from_cname(&nameserver).ns_name().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn ptr_record() -> Result<()> {
+        let name: Name = "host.example.com".parse()?;
+        let record = Record::from_ptr(&name);
+
+        assert_eq!(b"\x00\x0c\x00\x01\x00\x12\x04host\x07example\x03com\x00", &record.to_vec()[..]);
+        assert_eq!(name, record.ptr_name()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn srv_record() -> Result<()> {
+        let target: Name = "sip.example.com".parse()?;
+        let record = Record::from_srv(10, 60, 5060, &target);
+
+        assert_eq!(33, u16::from(RRType::SRV));
+        assert_eq!(RRType::SRV, RRType::try_from(33)?);
+        assert_eq!(b"\x00\x21\x00\x01\x00\x17\x00\x0a\x00\x3c\x13\xc4\x03sip\x07example\x03com\x00", &record.to_vec()[..]);
+        assert_eq!(10, record.srv_priority()?);
+        assert_eq!(60, record.srv_weight()?);
+        assert_eq!(5060, record.srv_port()?);
+        assert_eq!(target, record.srv_target()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn txt_record() -> Result<()> {
+        let record = Record::from_txt(&["hello", "world"])?;
+
+        assert_eq!(b"\x00\x10\x00\x01\x00\x0c\x05hello\x05world", &record.to_vec()[..]);
+        assert_eq!(vec!["hello", "world"], record.txt_strings()?);
+        assert!(Record::from_txt(&["x".repeat(256).as_str()]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn aaaa_type_round_trip() -> Result<()> {
+        assert_eq!(28, u16::from(RRType::AAAA));
+        assert_eq!(RRType::AAAA, RRType::try_from(28)?);
+        assert_eq!(QType::RRType(RRType::AAAA), QType::try_from(28)?);
+        assert_eq!(QType::AXFR, QType::try_from(252)?);
+        assert_eq!(QType::ANY, QType::try_from(255)?);
+
+        Ok(())
+    }
 }
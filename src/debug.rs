@@ -0,0 +1,77 @@
+use std::{fmt::Write, sync::atomic::{AtomicBool, Ordering}};
+
+use once_cell::sync::Lazy;
+
+static BYTES_PER_ROW: usize = 16;
+static HEXDUMP_ON_ERROR: Lazy<bool> = Lazy::new(|| std::env::var_os("DNS_HEXDUMP").is_some());
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbose(enabled: bool) {
+    VERBOSE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+
+    for (row, chunk) in bytes.chunks(BYTES_PER_ROW).enumerate() {
+        write!(dump, "{:08x} ", row * BYTES_PER_ROW).unwrap();
+        for k in 0..BYTES_PER_ROW {
+            if k % 8 == 0 {
+                dump.push(' ');
+            }
+            match chunk.get(k) {
+                Some(byte) => write!(dump, "{byte:02x} ").unwrap(),
+                None => dump.push_str("   "),
+            }
+        }
+
+        let ascii = chunk.iter()
+                         .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                         .collect::<String>();
+        writeln!(dump, " |{ascii}|").unwrap();
+    }
+
+    dump
+}
+
+fn enabled() -> bool {
+    *HEXDUMP_ON_ERROR || VERBOSE.load(Ordering::Relaxed)
+}
+
+pub(crate) fn report(message: &str) {
+    if enabled() {
+        eprintln!("{message}");
+    }
+}
+
+/// Per-packet chatter, printed only in verbose mode. Takes `format_args!` so that
+/// nothing is formatted while it's off.
+pub(crate) fn trace(message: std::fmt::Arguments) {
+    if VERBOSE.load(Ordering::Relaxed) {
+        eprintln!("{message}");
+    }
+}
+
+pub(crate) fn report_parse_error(err: &anyhow::Error, message: &[u8]) {
+    if *HEXDUMP_ON_ERROR {
+        eprintln!("{err}\n{}", hexdump(message));
+    } else if VERBOSE.load(Ordering::Relaxed) {
+        eprintln!("{err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_format() {
+        let bytes = b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x0ccodecrafters\x02io\x00";
+
+        assert_eq!("\
+00000000  12 34 01 00 00 01 00 00  00 00 00 00 0c 63 6f 64  |.4...........cod|
+00000010  65 63 72 61 66 74 65 72  73 02 69 6f 00           |ecrafters.io.|
+", hexdump(bytes));
+        assert_eq!("", hexdump(&[]));
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::Result;
+
+use crate::common::{OpCode, ResponseCode};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Header {
+    pub id: u16,
+    pub qr: bool,
+    pub opcode: OpCode,
+    pub aa: bool,
+    pub tc: bool,
+    pub rd: bool,
+    pub ra: bool,
+    pub z: bool,
+    pub ad: bool,
+    pub cd: bool,
+    pub rcode: ResponseCode,
+    pub qdcount: u16,
+    pub ancount: u16,
+    pub nscount: u16,
+    pub arcount: u16,
+}
+
+impl Header {
+    pub fn to_bytes(&self) -> [u8; 12] {
+        let flag = |set: bool, bit: u8| if set { bit } else { 0 };
+        let oc: u8 = self.opcode.clone().into();
+        let rc: u8 = self.rcode.clone().into();
+        let mut bytes = [0; 12];
+
+        bytes[0..2].copy_from_slice(&self.id.to_be_bytes());
+        bytes[2] = flag(self.qr, 0x80) | (oc & 0x0f) << 3 | flag(self.aa, 0x04) | flag(self.tc, 0x02) | flag(self.rd, 0x01);
+        bytes[3] = flag(self.ra, 0x80) | flag(self.z, 0x40) | flag(self.ad, 0x20) | flag(self.cd, 0x10) | (rc & 0x0f);
+        bytes[4..6].copy_from_slice(&self.qdcount.to_be_bytes());
+        bytes[6..8].copy_from_slice(&self.ancount.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.nscount.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.arcount.to_be_bytes());
+
+        bytes
+    }
+}
+
+impl TryFrom<&[u8; 12]> for Header {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8; 12]) -> Result<Self> {
+        let count = |k: usize| u16::from_be_bytes([value[k], value[k + 1]]);
+
+        Ok(Header {
+            id: count(0),
+            qr: (value[2] & 0x80) != 0,
+            opcode: ((value[2] >> 3) & 0x0f).into(),
+            aa: (value[2] & 0x04) != 0,
+            tc: (value[2] & 0x02) != 0,
+            rd: (value[2] & 0x01) != 0,
+            ra: (value[3] & 0x80) != 0,
+            z: (value[3] & 0x40) != 0,
+            ad: (value[3] & 0x20) != 0,
+            cd: (value[3] & 0x10) != 0,
+            rcode: ResponseCode::try_from(value[3] & 0x0f)?,
+            qdcount: count(4),
+            ancount: count(6),
+            nscount: count(8),
+            arcount: count(10),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static RESPONSE_HEADER: &[u8; 12] = b"\x12\x34\x85\x83\x00\x01\x00\x02\x00\x03\x00\x04";
+
+    #[test]
+    fn decode_header() -> Result<()> {
+        let header = Header::try_from(RESPONSE_HEADER)?;
+
+        assert_eq!(Header {
+            id: 0x1234,
+            qr: true,
+            aa: true,
+            rd: true,
+            ra: true,
+            rcode: ResponseCode::NameError,
+            qdcount: 1,
+            ancount: 2,
+            nscount: 3,
+            arcount: 4,
+            ..Header::default()
+        }, header);
+
+        Ok(())
+    }
+
+    #[test]
+    fn encode_header() -> Result<()> {
+        assert_eq!(*RESPONSE_HEADER, Header::try_from(RESPONSE_HEADER)?.to_bytes());
+
+        let header = Header {
+            opcode: OpCode::Status,
+            tc: true,
+            z: true,
+            ad: true,
+            cd: true,
+            rcode: ResponseCode::NoError,
+            ..Header::default()
+        };
+        assert_eq!(b"\x00\x00\x12\x70\x00\x00\x00\x00\x00\x00\x00\x00", &header.to_bytes());
+        assert_eq!(header, Header::try_from(&header.to_bytes())?);
+
+        Ok(())
+    }
+}
@@ -1,3 +1,16 @@
 pub mod server;
+pub mod acl;
+pub mod cache;
+pub mod client;
 pub mod common;
+pub mod debug;
+pub mod header;
 pub mod message;
+pub mod querylog;
+pub mod ratelimit;
+pub mod stats;
+pub mod tcp;
+pub mod zone;
+
+#[cfg(test)]
+mod testutil;
@@ -1,25 +1,180 @@
-use dns_starter_rust::{common::Record, server::Server};
+use std::{net::SocketAddr, path::PathBuf, sync::atomic::AtomicBool, thread};
 
-use anyhow::Result;
+use dns_starter_rust::{common::Record, debug, server::{Mode, Server, ServerBuilder}};
 
-fn config_server(server: &mut Server) -> Result<()> {
-    server.add_record(
-        "codecrafters.io",
-        Record::from_ip_v4("8.8.8.8")?
-        );
+use anyhow::{Context, Result, bail};
+
+static MAX_TCP_CONNECTIONS: usize = 64;
+static USAGE: &str = "\
+Usage: dns-starter-rust [--address ADDR] [--port PORT] [--zone-file PATH]... [--upstream ADDR:PORT]... [--verbose]";
+
+#[cfg(unix)]
+mod hangup {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static RECEIVED: AtomicBool = AtomicBool::new(false);
+    const SIGHUP: i32 = 1;
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    extern "C" fn on_hangup(_: i32) {
+        RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn install() {
+        unsafe {
+            signal(SIGHUP, on_hangup);
+        }
+    }
+
+    pub fn received() -> bool {
+        RECEIVED.swap(false, Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    address: Option<String>,
+    port: Option<u16>,
+    zone_files: Vec<PathBuf>,
+    upstreams: Vec<SocketAddr>,
+    verbose: bool,
+}
+
+impl Options {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Options> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().with_context(|| format!("Missing value for {arg}"));
+
+            match arg.as_str() {
+                "--address" => options.address = Some(value()?),
+                "--port" => options.port = Some(value()?.parse().context("Invalid port")?),
+                "--zone-file" => options.zone_files.push(value()?.into()),
+                "--upstream" => options.upstreams.push(value()?.parse().context("Invalid upstream address")?),
+                "--verbose" => options.verbose = true,
+                other => bail!("Unknown argument {other}\n{USAGE}"),
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn builder(&self) -> ServerBuilder {
+        let mode = if self.upstreams.is_empty() { Mode::Authoritative } else { Mode::Hybrid };
+        let mut builder = ServerBuilder::default().mode(mode).set_tcp();
+
+        if let Some(address) = &self.address {
+            builder = builder.address(address);
+        }
+        if let Some(port) = self.port {
+            builder = builder.port(port);
+        }
+        for &upstream in &self.upstreams {
+            builder = builder.upstream(upstream);
+        }
+
+        builder.log_json(self.verbose)
+    }
+}
+
+fn config_server(server: &mut Server, options: &Options) -> Result<()> {
+    for path in &options.zone_files {
+        server.load_zone_file(path).with_context(|| format!("Failed to load {}", path.display()))?;
+    }
+
+    if options.zone_files.is_empty() {
+        server.add_record(
+            "codecrafters.io",
+            Record::from_ip_v4("8.8.8.8")?
+            )?;
+    }
 
     Ok(())
 }
 
 fn main() -> Result<()> {
-    let mut server = Server::new()?;
-    config_server(&mut server)?;
+    let options = Options::parse(std::env::args().skip(1))?;
+    debug::set_verbose(options.verbose);
+    let mut server = options.builder().build()?;
+    config_server(&mut server, &options)?;
 
-    eprintln!("{server:?}");
+    if options.verbose {
+        eprintln!("{server:?}");
+    }
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            if let Err(err) = server.serve_tcp_forever(MAX_TCP_CONNECTIONS, &AtomicBool::new(false)) {
+                eprintln!("{err}")
+            }
+        });
 
-    loop {
-        if let Err(err) = server.serve() {
-            eprintln!("{err}")
+        #[cfg(unix)]
+        {
+            hangup::install();
+            s.spawn(|| loop {
+                thread::sleep(std::time::Duration::from_secs(1));
+                if hangup::received() {
+                    match server.reload() {
+                        Ok(()) => eprintln!("Reloaded records"),
+                        Err(err) => eprintln!("Failed to reload records: {err}"),
+                    }
+                }
+            });
         }
+
+        server.serve_forever(4, &AtomicBool::new(false))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_arguments() -> Result<()> {
+        assert_eq!(Options::default(), Options::parse(vec![])?);
+        assert_eq!(Options {
+            address: Some("0.0.0.0".into()),
+            port: Some(5353),
+            zone_files: vec!["zones/example.zone".into(), "zones/other.zone".into()],
+            upstreams: vec!["8.8.8.8:53".parse()?, "1.1.1.1:53".parse()?],
+            verbose: true,
+        }, Options::parse(args("--port 5353 --zone-file zones/example.zone --address 0.0.0.0 \
+                                --verbose --zone-file zones/other.zone --upstream 8.8.8.8:53 --upstream 1.1.1.1:53"))?);
+
+        assert!(Options::parse(args("--port")).is_err());
+        assert!(Options::parse(args("--port 70000")).is_err());
+        assert!(Options::parse(args("--upstream 8.8.8.8")).is_err());
+        assert!(Options::parse(args("--bogus")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn arguments_map_to_builder() -> Result<()> {
+        let options = Options::parse(args("--address 0.0.0.0 --port 5353 --upstream 1.1.1.1:53 --verbose"))?;
+        let expected = ServerBuilder::default()
+            .mode(Mode::Hybrid)
+            .set_tcp()
+            .address("0.0.0.0")
+            .port(5353)
+            .upstream("1.1.1.1:53".parse()?)
+            .log_json(true);
+
+        assert_eq!(format!("{expected:?}"), format!("{:?}", options.builder()));
+        assert_eq!(format!("{:?}", ServerBuilder::default().mode(Mode::Authoritative).set_tcp()),
+                   format!("{:?}", Options::default().builder()));
+
+        Ok(())
     }
 }
@@ -1,7 +1,15 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::{collections::{hash_map::Entry, HashMap}, fmt::Display};
 
 use anyhow::{Result, bail};
-use crate::common::{Name, OpCode, QClass, QType, Record, ResponseCode};
+use crate::{
+    common::{Name, OpCode, QClass, QType, RRClass, RRType, Record, ResponseCode},
+    debug,
+    header::Header,
+};
+
+static MAX_POINTER_OFFSET: usize = 0x3fff;
+pub static EDNS_NSID: u16 = 3;
+pub static EDNS_COOKIE: u16 = 10;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Question {
@@ -11,6 +19,10 @@ pub struct Question {
 }
 
 impl Question {
+    pub fn new(qname: Name, qtype: QType, qclass: QClass) -> Self {
+        Question { qname, qtype, qclass }
+    }
+
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         self.qname.len() + 4
@@ -20,7 +32,15 @@ impl Question {
         &self.qname
     }
 
-    pub fn expand(&self, references: &HashMap<u16, Vec<String>>) -> Result<Question> {
+    pub fn qtype(&self) -> &QType {
+        &self.qtype
+    }
+
+    pub fn qclass(&self) -> &QClass {
+        &self.qclass
+    }
+
+    pub fn expand(&self, references: &HashMap<u16, Name>) -> Result<Question> {
         Ok(Question {
             qname: self.qname.expand(references)?,
             ..self.clone()
@@ -43,6 +63,27 @@ impl Question {
     }
 }
 
+impl Question {
+    fn decode(qname: Name, value: &[u8], meta: usize) -> Result<(Question, usize)> {
+        let query_end = meta + 4;
+
+        if query_end > value.len() {
+            bail!("Corrupt message: truncated question");
+        }
+
+        let qtype = QType::try_from(u16::from_be_bytes([value[meta], value[meta + 1]]))?;
+        let qclass = QClass::try_from(u16::from_be_bytes([value[meta + 2], value[meta + 3]]))?;
+
+        Ok((Question { qname, qtype, qclass }, query_end))
+    }
+
+    pub fn from_message(message: &[u8], offset: usize) -> Result<(Question, usize)> {
+        let (qname, meta) = Name::from_message(message, offset)?;
+
+        Question::decode(qname, message, meta)
+    }
+}
+
 impl TryFrom<&[u8]> for Question {
     type Error = anyhow::Error;
 
@@ -50,19 +91,222 @@ impl TryFrom<&[u8]> for Question {
         if !value.is_empty() {
             let qname = Name::try_from(value)?;
             let meta = qname.len();
-            let query_end = meta + 4;
 
-            if query_end > value.len() {
-                bail!("Corrupt message: truncated question");
+            Ok(Question::decode(qname, value, meta)?.0)
+        } else {
+            bail!("Empty question")
+        }
+    }
+}
+
+struct MessageParser<'a> {
+    value: &'a [u8],
+    ptr: usize,
+}
+
+impl<'a> MessageParser<'a> {
+    fn new(value: &'a [u8]) -> Self {
+        MessageParser { value, ptr: 12 }
+    }
+
+    fn question(&mut self) -> Result<Question> {
+        let (question, next) = Question::from_message(self.value, self.ptr)?;
+        self.ptr = next;
+
+        Ok(question)
+    }
+
+    fn answer(&mut self) -> Result<Answer> {
+        let (answer, next) = Answer::from_message(self.value, self.ptr)?;
+        self.ptr = next;
+
+        Ok(answer)
+    }
+
+    fn exhausted(&self) -> bool {
+        self.ptr >= self.value.len()
+    }
+}
+
+struct MessageWriter {
+    buffer: Vec<u8>,
+    references: HashMap<Vec<String>, u16>,
+}
+
+impl MessageWriter {
+    fn new(header: Vec<u8>) -> Self {
+        MessageWriter {
+            buffer: header,
+            references: HashMap::new(),
+        }
+    }
+
+    fn register(&mut self, name: &Name) {
+        self.register_at(name, self.buffer.len());
+    }
+
+    fn register_at(&mut self, name: &Name, offset: usize) {
+        let labels = name.labels();
+        let mut ref_ptr = offset;
+        for start in 0..labels.len() {
+            if ref_ptr > MAX_POINTER_OFFSET {
+                break;
             }
 
-            let qtype = QType::try_from(u16::from_be_bytes([value[meta], value[meta + 1]]))?;
-            let qclass = QClass::try_from(u16::from_be_bytes([value[meta + 2], value[meta + 3]]))?;
+            let partial = &labels[start..];
+            if let Entry::Vacant(v) = self.references.entry(partial.to_vec()) {
+                v.insert(ref_ptr as u16);
+            }
 
-            Ok(Question { qname, qtype, qclass })
-        } else {
-            bail!("Empty question")
+            ref_ptr += partial[0].len() + 1;
+        }
+    }
+
+    fn question(&mut self, question: &Question) -> Result<()> {
+        question.name().validate()?;
+        let compressed = question.compress(&self.references)?.to_vec();
+        self.register(question.name());
+        self.buffer.extend(compressed);
+
+        Ok(())
+    }
+
+    fn answer(&mut self, answer: &Answer) -> Result<()> {
+        answer.name().validate()?;
+        let mut compressed = answer.compress(&self.references)?;
+        self.register(answer.name());
+
+        let record = answer.record();
+        let embedded = record.embedded_names();
+        if embedded.is_none() && record.name_layout().is_some() {
+            bail!("Corrupt record: {} RDATA doesn't hold a valid name", record.rrtype());
+        }
+
+        if let Some((offset, names, end)) = embedded {
+            let data_start = self.buffer.len() + compressed.name().to_vec().len() + 10;
+            let mut data = record.data()[..offset].to_vec();
+            for name in names {
+                let encoded = name.compress(&self.references)?.to_vec();
+                self.register_at(&name, data_start + data.len());
+                data.extend(encoded);
+            }
+            data.extend(&record.data()[end..]);
+
+            compressed.record = record.with_data(data);
+        }
+
+        self.buffer.extend(compressed.to_vec());
+
+        Ok(())
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edns {
+    payload_size: u16,
+    extended_rcode: u8,
+    version: u8,
+    dnssec_ok: bool,
+    options: Vec<(u16, Vec<u8>)>,
+}
+
+impl Edns {
+    pub fn new(payload_size: u16) -> Self {
+        Edns {
+            payload_size,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: vec![],
+        }
+    }
+
+    pub fn payload_size(&self) -> u16 {
+        self.payload_size
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn with_version(mut self, version: u8) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn dnssec_ok(&self) -> bool {
+        self.dnssec_ok
+    }
+
+    pub fn set_dnssec_ok(mut self) -> Self {
+        self.dnssec_ok = true;
+        self
+    }
+
+    pub fn options(&self) -> &[(u16, Vec<u8>)] {
+        &self.options
+    }
+
+    pub fn option(mut self, code: u16, data: &[u8]) -> Self {
+        self.options.push((code, data.to_vec()));
+        self
+    }
+
+    pub fn has_option(&self, code: u16) -> bool {
+        self.options.iter().any(|(c, _)| *c == code)
+    }
+
+    pub fn has_cookie(&self) -> bool {
+        self.has_option(EDNS_COOKIE)
+    }
+
+    pub fn has_nsid(&self) -> bool {
+        self.has_option(EDNS_NSID)
+    }
+
+    pub fn from_answer(answer: &Answer) -> Option<Edns> {
+        if *answer.record().rrtype() != RRType::OPT {
+            return None;
+        }
+
+        // Options are (code, length, data) triplets; a truncated trailing one is ignored
+        let mut options = vec![];
+        let mut data = &answer.record().data()[..];
+        while let [c0, c1, l0, l1, rest @ ..] = data {
+            let length = u16::from_be_bytes([*l0, *l1]) as usize;
+            let Some(value) = rest.get(..length) else {
+                break;
+            };
+            options.push((u16::from_be_bytes([*c0, *c1]), value.to_vec()));
+            data = &rest[length..];
         }
+
+        let ttl = answer.ttl();
+        Some(Edns {
+            payload_size: answer.record().rrclass().clone().into(),
+            extended_rcode: (ttl >> 24) as u8,
+            version: (ttl >> 16) as u8,
+            dnssec_ok: ttl & 0x8000 != 0,
+            options,
+        })
+    }
+
+    pub fn to_answer(&self) -> Answer {
+        let ttl = (self.extended_rcode as u32) << 24
+                | (self.version as u32) << 16
+                | if self.dnssec_ok { 0x8000 } else { 0 };
+        let rrclass = RRClass::try_from(self.payload_size).unwrap_or(RRClass::Unknown(self.payload_size));
+        let data = self.options
+                       .iter()
+                       .flat_map(|(code, value)| [&code.to_be_bytes()[..], &(value.len() as u16).to_be_bytes(), value].concat())
+                       .collect();
+        let record = Record::new(RRType::OPT, rrclass, data).with_ttl(ttl);
+
+        Answer::new(&Name::from(Vec::<String>::new()), &record)
     }
 }
 
@@ -73,8 +317,10 @@ pub struct Query {
     opcode: OpCode,
     truncation: bool,
     recursion_desired: bool,
+    authentic_data: bool,
+    checking_disabled: bool,
     questions: Vec<Question>,
-    dict: HashMap<u16, Vec<String>>,
+    edns: Option<Edns>,
 }
 
 impl Query {
@@ -85,6 +331,10 @@ impl Query {
         }
     }
 
+    pub fn builder() -> QueryBuilder {
+        QueryBuilder::default()
+    }
+
     pub fn id(&self) -> u16 {
         self.id
     }
@@ -97,87 +347,129 @@ impl Query {
         self.recursion_desired
     }
 
+    pub fn authentic_data(&self) -> bool {
+        self.authentic_data
+    }
+
+    pub fn checking_disabled(&self) -> bool {
+        self.checking_disabled
+    }
+
     pub fn questions(&self) -> Vec<Question> {
         self.questions.clone()
     }
 
+    pub fn questions_ref(&self) -> &[Question] {
+        &self.questions
+    }
+
+    pub fn into_questions(self) -> Vec<Question> {
+        self.questions
+    }
+
     pub fn response_code(&self) -> ResponseCode {
         self.response_code.clone()
     }
-}
 
-impl TryFrom<&[u8]> for Query {
-    type Error = anyhow::Error;
+    pub fn edns(&self) -> Option<&Edns> {
+        self.edns.as_ref()
+    }
 
-    fn try_from(value: &[u8]) -> Result<Self> {
+    pub fn header(&self) -> Header {
+        Header {
+            id: self.id,
+            opcode: self.opcode.clone(),
+            tc: self.truncation,
+            rd: self.recursion_desired,
+            ad: self.authentic_data,
+            cd: self.checking_disabled,
+            rcode: ResponseCode::NoError,
+            qdcount: self.questions.len() as u16,
+            arcount: self.edns.is_some() as u16,
+            ..Header::default()
+        }
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut writer = MessageWriter::new(self.header().to_bytes().to_vec());
+        for question in &self.questions {
+            writer.question(question)?;
+        }
+
+        if let Some(edns) = &self.edns {
+            writer.answer(&edns.to_answer())?;
+        }
+
+        Ok(writer.finish())
+    }
+
+    pub fn parse(value: &[u8], strict: bool) -> Result<Self> {
         if value.len() < 2 {
             bail!("Not even an id!");
         }
 
-        let mut query = Query::new();
+        let mut padded = [0; 12];
+        let available = value.len().min(12);
+        padded[..available].copy_from_slice(&value[..available]);
+        let header = Header::try_from(&padded)?;
 
-        query.id = u16::from_be_bytes([value[0], value[1]]);
+        let mut query = Query::new();
+        query.id = header.id;
 
         if value.len() > 2 {
-            if (value[2] & 0x80) != 0 {
+            if header.qr {
                 bail!("This is a response, not a query!");
             }
-            query.opcode = ((value[2] >> 3) & 0x0f).into();
-            query.truncation = (value[2] & 0x2) == 0x2;
-            query.recursion_desired = (value[2] & 0x1) == 0x1;
+            query.opcode = header.opcode;
+            query.truncation = header.tc;
+            query.recursion_desired = header.rd;
         }
 
-        if value.len() > 3 && ((value[3] & 0xf0) != 0) {
-            eprintln!("Field 'Z' is not all zeros");
-            return Ok(query);
+        if value.len() > 3 {
+            if header.z {
+                debug::report("Field 'Z' is not zero");
+                return Ok(query);
+            }
+            query.authentic_data = header.ad;
+            query.checking_disabled = header.cd;
         }
 
         if value.len() < 12 {
-            eprintln!("Truncated package with len: {}", value.len());
+            debug::report(&format!("Truncated package with len: {}", value.len()));
             return Ok(query);
         }
 
-        let mut ref_store: HashMap<u16, Vec<String>> = HashMap::new();
-        let mut qdcount = u16::from_be_bytes([value[4], value[5]]);
-        let mut ptr = 12;
-        while qdcount > 0 {
-            if ptr >= value.len() {
-                eprintln!("Truncated message: query count larger than contents");
-                return Ok(query)
-            }
-
-            let question = match Question::try_from(&value[ptr..]) {
-                Ok(q) => q,
-                Err(err) => { println!("{err}"); return Ok(query); }
-            };
-
-            let n_labels = question.name().labels().len();
-
-            let delta = question.len();
-
-            let expanded_question = {
-                if question.name().pointer().is_some() {
-                    question.expand(&ref_store)?
-                } else {
-                    question
-                }
-            };
-
-            let expanded_labels = expanded_question.name().labels().clone();
-
-            let mut ref_ptr = ptr as u16;
-            for start in 0..n_labels {
-                let partial = &expanded_labels[start..];
-                ref_store.insert(ref_ptr, partial.to_vec());
+        if strict && header.qdcount == 0 {
+            debug::report("Query without questions");
+            return Ok(query);
+        }
 
-                ref_ptr += (partial[0].len() + 1) as u16;
+        let mut parser = MessageParser::new(value);
+        for _ in 0..header.qdcount {
+            if !strict && parser.exhausted() {
+                break;
             }
+            match parser.question() {
+                Ok(question) => query.questions.push(question),
+                Err(err) => { debug::report_parse_error(&err, value); return Ok(query); }
+            }
+        }
 
-            ref_store.insert(ptr as u16, expanded_question.name().labels().clone());
+        let skipped = header.ancount as usize + header.nscount as usize;
+        for k in 0..skipped + header.arcount as usize {
+            if !strict && parser.exhausted() {
+                break;
+            }
+            match parser.answer() {
+                Ok(record) if k >= skipped => query.edns = Edns::from_answer(&record).or(query.edns),
+                Ok(_) => {},
+                Err(err) => { debug::report_parse_error(&err, value); return Ok(query); }
+            }
+        }
 
-            ptr += delta;
-            qdcount -= 1;
-            query.questions.push(expanded_question);
+        if strict && !parser.exhausted() {
+            debug::report("Trailing data after the declared sections");
+            return Ok(query);
         }
 
         query.response_code = match query.opcode {
@@ -189,26 +481,124 @@ impl TryFrom<&[u8]> for Query {
     }
 }
 
-#[derive(Clone, Debug)]
+impl TryFrom<&[u8]> for Query {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        Query::parse(value, false)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct QueryBuilder {
+    id: u16,
+    opcode: OpCode,
+    recursion_desired: bool,
+    authentic_data: bool,
+    checking_disabled: bool,
+    questions: Vec<Question>,
+    edns: Option<Edns>,
+}
+
+impl QueryBuilder {
+    pub fn build(self) -> Query {
+        Query {
+            response_code: match self.opcode {
+                OpCode::Query => ResponseCode::NoError,
+                _ => ResponseCode::NotImplemented,
+            },
+            id: self.id,
+            opcode: self.opcode,
+            recursion_desired: self.recursion_desired,
+            authentic_data: self.authentic_data,
+            checking_disabled: self.checking_disabled,
+            questions: self.questions,
+            edns: self.edns,
+            ..Query::new()
+        }
+    }
+
+    pub fn id(mut self, id: u16) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn opcode(mut self, opcode: OpCode) -> Self {
+        self.opcode = opcode;
+        self
+    }
+
+    pub fn recursion_desired(mut self, recursion_desired: bool) -> Self {
+        self.recursion_desired = recursion_desired;
+        self
+    }
+
+    pub fn authentic_data(mut self, authentic_data: bool) -> Self {
+        self.authentic_data = authentic_data;
+        self
+    }
+
+    pub fn checking_disabled(mut self, checking_disabled: bool) -> Self {
+        self.checking_disabled = checking_disabled;
+        self
+    }
+
+    pub fn questions(mut self, questions: Vec<Question>) -> Self {
+        self.questions = questions;
+        self
+    }
+
+    pub fn edns(mut self, edns: Edns) -> Self {
+        self.edns = Some(edns);
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Answer {
     name: Name,
     record: Record,
-    ttl: u32,
 }
 
 impl Answer {
-    pub fn new(name: &Name, record: &Record, ttl: u32) -> Self {
+    pub fn new(name: &Name, record: &Record) -> Self {
         Answer {
             name: name.clone(),
             record: record.clone(),
-            ttl
         }
     }
 
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.name.len() + 10 + self.record.data().len()
+    }
+
     pub fn name(&self) -> &Name {
         &self.name
     }
 
+    pub fn record(&self) -> &Record {
+        &self.record
+    }
+
+    pub fn ttl(&self) -> u32 {
+        self.record.ttl()
+    }
+
+    pub fn with_ttl(&self, ttl: u32) -> Answer {
+        Answer {
+            name: self.name.clone(),
+            record: self.record.clone().with_ttl(ttl),
+        }
+    }
+
+    pub fn expand(&self, references: &HashMap<u16, Name>) -> Result<Answer> {
+        Ok(Answer {
+            name: self.name.expand(references)?,
+            ..self.clone()
+        })
+    }
+
     pub fn compress(&self, references: &HashMap<Vec<String>, u16>) -> Result<Answer> {
         Ok(Answer {
             name: self.name.compress(references)?,
@@ -220,14 +610,79 @@ impl Answer {
         [self.name.to_vec(),
          u16::to_be_bytes(self.record.rrtype().clone().into()).to_vec(),
          u16::to_be_bytes(self.record.rrclass().clone().into()).to_vec(),
-         u32::to_be_bytes(self.ttl).to_vec(),
+         u32::to_be_bytes(self.record.ttl()).to_vec(),
          u16::to_be_bytes(self.record.data().len() as u16).to_vec(),
          self.record.data().clone()
         ].iter().flatten().cloned().collect()
     }
 }
 
-#[derive(Debug, Clone)]
+impl Answer {
+    fn decode(name: Name, value: &[u8], meta: usize) -> Result<(Answer, usize)> {
+        if meta + 10 > value.len() {
+            bail!("Corrupt message: truncated resource record");
+        }
+
+        let field = |k: usize| u16::from_be_bytes([value[meta + k], value[meta + k + 1]]);
+        let rrtype = RRType::try_from(field(0))?;
+        let rrclass = RRClass::try_from(field(2))?;
+        let ttl = u32::from_be_bytes([value[meta + 4], value[meta + 5], value[meta + 6], value[meta + 7]]);
+        let data_end = meta + 10 + field(8) as usize;
+
+        if data_end > value.len() {
+            bail!("Corrupt message: truncated resource record data");
+        }
+
+        let answer = Answer {
+            name,
+            record: Record::new(rrtype, rrclass, value[meta + 10..data_end].to_vec()).with_ttl(ttl),
+        };
+
+        Ok((answer, data_end))
+    }
+
+    pub fn from_message(message: &[u8], offset: usize) -> Result<(Answer, usize)> {
+        let (name, meta) = Name::from_message(message, offset)?;
+        let (mut answer, end) = Answer::decode(name, message, meta)?;
+
+        if let Some((offset, count)) = answer.record.name_layout() {
+            let record = &answer.record;
+            let mut pos = meta + 10 + offset;
+            if pos > end {
+                bail!("Corrupt message: resource record data is too short");
+            }
+
+            let mut data = record.data()[..offset].to_vec();
+            for _ in 0..count {
+                let (name, next) = Name::from_message(message, pos)?;
+                data.extend(name.to_vec());
+                pos = next;
+            }
+
+            if pos > end {
+                bail!("Corrupt message: resource record data doesn't match its length");
+            }
+
+            data.extend(&message[pos..end]);
+            answer.record = record.with_data(data);
+        }
+
+        Ok((answer, end))
+    }
+}
+
+impl TryFrom<&[u8]> for Answer {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        let name = Name::try_from(value)?;
+        let meta = name.len();
+
+        Ok(Answer::decode(name, value, meta)?.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Response {
     id: u16,
     opcode: OpCode,
@@ -235,73 +690,258 @@ pub struct Response {
     authoritative_answer: bool,
     recursion_desired: bool,
     recursion_available: bool,
+    authentic_data: bool,
+    checking_disabled: bool,
     response_code: ResponseCode,
     questions: Vec<Question>,
     answers: Vec<Answer>,
+    authority: Vec<Answer>,
+    additional: Vec<Answer>,
 }
 
 impl Response {
     pub fn builder() -> ResponseBuilder {
         ResponseBuilder::default()
     }
+
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    pub fn response_code(&self) -> ResponseCode {
+        self.response_code.clone()
+    }
+
+    pub fn header(&self) -> Header {
+        Header {
+            id: self.id,
+            qr: true,
+            opcode: self.opcode.clone(),
+            aa: self.authoritative_answer,
+            tc: self.truncation,
+            rd: self.recursion_desired,
+            ra: self.recursion_available,
+            z: false,
+            ad: self.authentic_data,
+            cd: self.checking_disabled,
+            rcode: self.response_code.clone(),
+            qdcount: self.questions.len() as u16,
+            ancount: self.answers.len() as u16,
+            nscount: self.authority.len() as u16,
+            arcount: self.additional.len() as u16,
+        }
+    }
+
+    pub fn truncation(&self) -> bool {
+        self.truncation
+    }
+
+    pub fn truncated(&self) -> Response {
+        Response {
+            truncation: true,
+            answers: vec![],
+            authority: vec![],
+            additional: self.additional
+                            .iter()
+                            .filter(|a| *a.record().rrtype() == RRType::OPT)
+                            .cloned()
+                            .collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn edns(&self) -> Option<Edns> {
+        self.additional.iter().find_map(Edns::from_answer)
+    }
+
+    pub fn encode_into(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let rcode: u8 = self.response_code.clone().into();
+        let extended = (rcode >> 4) as u32;
+        let is_opt = |a: &&Answer| *a.record().rrtype() == RRType::OPT;
+
+        if extended != 0 && !self.additional.iter().any(|a| is_opt(&a)) {
+            bail!("Response code {rcode} needs an OPT record to be encoded");
+        }
+
+        buf.clear();
+        buf.extend_from_slice(&self.header().to_bytes());
+        let mut writer = MessageWriter::new(std::mem::take(buf));
+        for question in &self.questions {
+            writer.question(question)?;
+        }
+
+        for answer in self.answers.iter().chain(&self.authority).chain(&self.additional) {
+            if is_opt(&answer) {
+                writer.answer(&answer.with_ttl(answer.ttl() & 0x00ff_ffff | extended << 24))?;
+            } else {
+                writer.answer(answer)?;
+            }
+        }
+
+        *buf = writer.finish();
+        Ok(())
+    }
+
+    pub fn questions(&self) -> &[Question] {
+        &self.questions
+    }
+
+    pub fn answers(&self) -> &[Answer] {
+        &self.answers
+    }
+
+    pub fn authority(&self) -> &[Answer] {
+        &self.authority
+    }
+
+    pub fn additional(&self) -> &[Answer] {
+        &self.additional
+    }
 }
 
-impl From<Response> for Vec<u8> {
-    fn from(value: Response) -> Self {
-        let oc: u8 = value.opcode.into();
-        let aa = if value.authoritative_answer { 4u8 } else { 0 };
-        let tc = if value.truncation { 2u8 } else { 0 };
-        let rd = if value.recursion_desired { 1u8 } else { 0 };
-        let rc = value.response_code as u8;
-        let ra = if value.recursion_available { 0x80u8 } else { 0 };
-        let qdcount = u16::to_be_bytes(value.questions.len() as u16);
-        let ancount = u16::to_be_bytes(value.answers.len() as u16);
-
-        let mut res = vec![
-            (value.id >> 8) as u8, (value.id & 0xff) as u8,
-            (0x80 | oc << 3 | aa | tc | rd) , ra | rc,
-            qdcount[0], qdcount[1],
-            ancount[0], ancount[1],
-            0, 0,
-            0, 0,
-        ];
+impl TryFrom<&[u8]> for Response {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        let Some(bytes) = value.first_chunk::<12>() else {
+            bail!("Corrupt message: truncated header");
+        };
+        let header = Header::try_from(bytes)?;
+
+        if !header.qr {
+            bail!("This is a query, not a response!");
+        }
 
-        let mut ref_store: HashMap::<Vec<String>, u16> = HashMap::new();
-        let mut ptr: u16 = 12;
-        for question in value.questions {
-            let compressed = question.compress(&ref_store).unwrap().to_vec();
-            let labels = question.name().labels().clone();
-            let mut ref_ptr = ptr;
-            for start in 0..labels.len() {
-                let partial = &labels[start..];
-                if let Entry::Vacant(v) = ref_store.entry(partial.to_vec()) {
-                    v.insert(ref_ptr);
-                }
-
-                ref_ptr += (partial[0].len() + 1) as u16;
+        let mut parser = MessageParser::new(value);
+
+        let questions = (0..header.qdcount).map(|_| parser.question()).collect::<Result<Vec<_>>>()?;
+        let answers = (0..header.ancount).map(|_| parser.answer()).collect::<Result<Vec<_>>>()?;
+        let authority = (0..header.nscount).map(|_| parser.answer()).collect::<Result<Vec<_>>>()?;
+        let mut additional = (0..header.arcount).map(|_| parser.answer()).collect::<Result<Vec<_>>>()?;
+
+        let mut response_code = header.rcode;
+        for opt in additional.iter_mut().filter(|a| *a.record().rrtype() == RRType::OPT) {
+            let extended = (opt.ttl() >> 24) as u8;
+            if extended != 0 {
+                let low: u8 = response_code.into();
+                let Ok(code) = u8::try_from((extended as u16) << 4 | low as u16) else {
+                    bail!("Corrupt message: unsupported extended RCode");
+                };
+                response_code = ResponseCode::try_from(code)?;
             }
-            ptr += compressed.len() as u16;
-            res.extend(compressed);
+            *opt = opt.with_ttl(opt.ttl() & 0x00ff_ffff);
+        }
+
+        Ok(Response {
+            id: header.id,
+            opcode: header.opcode,
+            truncation: header.tc,
+            authoritative_answer: header.aa,
+            recursion_desired: header.rd,
+            recursion_available: header.ra,
+            authentic_data: header.ad,
+            checking_disabled: header.cd,
+            response_code,
+            questions,
+            answers,
+            authority,
+            additional,
+        })
+    }
+}
+
+impl Display for Answer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\t{}", self.name, self.record)
+    }
+}
+
+impl Display for Response {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header = self.header();
+        let flags = [
+            ("qr", header.qr), ("aa", header.aa), ("tc", header.tc), ("rd", header.rd),
+            ("ra", header.ra), ("ad", header.ad), ("cd", header.cd),
+        ];
+        let flags = flags.iter().filter(|(_, set)| *set).map(|(flag, _)| *flag).collect::<Vec<_>>();
+        let is_opt = |a: &&Answer| *a.record().rrtype() == RRType::OPT;
+        let additional = self.additional.iter().filter(|a| !is_opt(a)).collect::<Vec<_>>();
+
+        writeln!(f, ";; ->>HEADER<<- opcode: {}, status: {}, id: {}", header.opcode, self.response_code, header.id)?;
+        writeln!(f, ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+                 flags.join(" "), header.qdcount, header.ancount, header.nscount, header.arcount)?;
+
+        if let Some(edns) = self.edns() {
+            writeln!(f, "\n;; OPT PSEUDOSECTION:")?;
+            writeln!(f, "; EDNS: version: {}, flags:{}; udp: {}",
+                     edns.version(), if edns.dnssec_ok() { " do" } else { "" }, edns.payload_size())?;
+        }
+
+        writeln!(f, "\n;; QUESTION SECTION:")?;
+        for question in &self.questions {
+            writeln!(f, ";{}\t\t{}\t{}", question.qname, question.qclass, question.qtype)?;
         }
 
-        for answer in value.answers {
-            let compressed = answer.compress(&ref_store).unwrap().to_vec();
-            let labels = answer.name().labels().clone();
-            let mut ref_ptr = ptr;
-            for start in 0..labels.len() {
-                let partial = &labels[start..];
-                if let Entry::Vacant(v) = ref_store.entry(partial.to_vec()) {
-                    v.insert(ref_ptr);
-                }
+        for (title, section) in [("ANSWER", self.answers.iter().collect()), ("AUTHORITY", self.authority.iter().collect()), ("ADDITIONAL", additional)] {
+            if section.is_empty() {
+                continue;
+            }
 
-                ref_ptr += (partial[0].len() + 1) as u16;
+            writeln!(f, "\n;; {title} SECTION:")?;
+            for answer in section {
+                writeln!(f, "{answer}")?;
             }
-            ptr += compressed.len() as u16;
-            res.extend(compressed);
         }
 
-        res
+        Ok(())
+    }
+}
+
+impl TryFrom<Response> for Vec<u8> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Response) -> Result<Self> {
+        let mut buf = vec![];
+        value.encode_into(&mut buf)?;
+
+        Ok(buf)
+    }
+}
+
+pub fn validate_message_bytes(value: &[u8]) -> Result<()> {
+    if value.len() < 12 {
+        bail!("Corrupt message: truncated header");
+    }
+
+    let count = |k: usize| u16::from_be_bytes([value[k], value[k + 1]]) as usize;
+    let qdcount = count(4);
+    let rrcount = count(6) + count(8) + count(10);
+
+    let mut ptr = 12;
+    for _ in 0..qdcount {
+        ptr = Name::from_message(value, ptr)?.1 + 4;
+        if ptr > value.len() {
+            bail!("Corrupt message: truncated question");
+        }
+    }
+
+    for _ in 0..rrcount {
+        ptr = Name::from_message(value, ptr)?.1;
+        if ptr + 10 > value.len() {
+            bail!("Corrupt message: truncated resource record");
+        }
+        let rdlength = u16::from_be_bytes([value[ptr + 8], value[ptr + 9]]) as usize;
+        ptr += 10 + rdlength;
+        if ptr > value.len() {
+            bail!("Corrupt message: truncated resource record data");
+        }
+    }
+
+    if ptr != value.len() {
+        bail!("Corrupt message: {} bytes beyond the declared sections", value.len() - ptr);
     }
+
+    Ok(())
 }
 
 #[derive(Debug, Default)]
@@ -312,9 +952,13 @@ pub struct ResponseBuilder {
     authoritative_answer: bool,
     recursion_desired: bool,
     recursion_available: bool,
+    authentic_data: bool,
+    checking_disabled: bool,
     response_code: ResponseCode,
     questions: Vec<Question>,
     answers: Vec<Answer>,
+    authority: Vec<Answer>,
+    additional: Vec<Answer>,
 }
 
 impl ResponseBuilder {
@@ -326,9 +970,13 @@ impl ResponseBuilder {
             authoritative_answer: self.authoritative_answer,
             recursion_desired: self.recursion_desired,
             recursion_available: self.recursion_available,
+            authentic_data: self.authentic_data,
+            checking_disabled: self.checking_disabled,
             response_code: self.response_code,
             questions: self.questions,
             answers: self.answers,
+            authority: self.authority,
+            additional: self.additional,
         }
     }
 
@@ -347,6 +995,16 @@ impl ResponseBuilder {
         self
     }
 
+    pub fn authentic_data(mut self, authentic_data: bool) -> Self {
+        self.authentic_data = authentic_data;
+        self
+    }
+
+    pub fn checking_disabled(mut self, checking_disabled: bool) -> Self {
+        self.checking_disabled = checking_disabled;
+        self
+    }
+
     pub fn set_truncation(mut self) -> Self {
         self.truncation = true;
         self
@@ -376,16 +1034,27 @@ impl ResponseBuilder {
         self.answers = answers;
         self
     }
+
+    pub fn authority(mut self, authority: Vec<Answer>) -> Self {
+        self.authority = authority;
+        self
+    }
+
+    pub fn additional(mut self, additional: Vec<Answer>) -> Self {
+        self.additional = additional;
+        self
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
     use once_cell::sync::Lazy;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
     use std::iter::zip;
 
     use super::*;
-    use crate::common::{RRType, RRClass};
+    use crate::testutil::allocations;
 
     static SAMPLE_BIN_QUERIES: &[&[u8]] = &[
         b"\xfd\xf0\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x0ccodecrafters\x02io\x00\x00\x01\x00\x01",
@@ -410,7 +1079,6 @@ mod tests {
         Answer {
             name: Name::from(vec!["codecrafters", "io"]),
             record: Record::from_ip_v4("8.8.8.8").expect("Not a valid IPv4"),
-            ttl: 60,
         }
     });
 
@@ -446,6 +1114,60 @@ mod tests {
         ]
     });
 
+    #[test]
+    fn compression_pointers_stay_within_14_bits() -> Result<()> {
+        let filler = "x".repeat(250);
+        let answers = (0..80).flat_map(|k| {
+            let name = Name::from(vec![format!("host{k}"), "example".into(), "org".into()]);
+            let record = Record::from_txt(&[&filler]).unwrap();
+            [Answer::new(&name, &record), Answer::new(&name, &record)]
+        }).collect::<Vec<_>>();
+        let response = Response::builder()
+            .id(0x4242)
+            .questions(vec![SAMPLE_QUESTION.clone()])
+            .answers(answers.clone())
+            .build();
+
+        let bytes = Vec::<u8>::try_from(response)?;
+        assert!(bytes.len() > 0x4000 * 2);
+        assert_eq!(answers, Response::try_from(&bytes[..])?.answers());
+
+        Ok(())
+    }
+
+    #[test]
+    fn oversized_names_are_not_encoded() -> Result<()> {
+        let label = "a".repeat(63);
+        let long = Name::from(vec![label.as_str(); 4]);
+        let record = Record::from_ip_v4("192.0.2.1")?;
+        let encode = |answers: Vec<Answer>| {
+            Vec::<u8>::try_from(Response::builder().id(0x4242).questions(vec![SAMPLE_QUESTION.clone()]).answers(answers).build())
+        };
+
+        assert!(long.validate().is_err());
+        assert!(encode(vec![Answer::new(&long, &record)]).is_err());
+        assert!(encode(vec![Answer::new(SAMPLE_QUESTION.name(), &Record::from_cname(&long))]).is_err());
+        assert!(encode(vec![Answer::new(SAMPLE_QUESTION.name(), &Record::from_cname(&Name::from(vec!["a".repeat(64)])))]).is_err());
+        let question = Question::new(long, QType::RRType(RRType::A), QClass::RRClass(RRClass::IN));
+        assert!(Query::builder().id(0x4242).questions(vec![question]).build().to_vec().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn display_response() {
+        assert_eq!("\
+;; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 65008
+;; flags: qr rd; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 0
+
+;; QUESTION SECTION:
+;codecrafters.io.\t\tIN\tA
+
+;; ANSWER SECTION:
+codecrafters.io.\t60\tIN\tA\t8.8.8.8
+", SAMPLE_RESPONSES[1].to_string());
+    }
+
     #[test]
     fn encode_decode_question() -> Result<()> {
         let question = Question::try_from(SAMPLE_BIN_QUESTION)?;
@@ -468,19 +1190,464 @@ mod tests {
     }
 
     #[test]
-    fn build_response() {
+    fn build_response() -> Result<()> {
         for (&target, response) in zip(SAMPLE_BIN_RESPONSES, SAMPLE_RESPONSES.clone()) {
-            let bin: Vec<u8> = response.into();
+            let bin: Vec<u8> = response.try_into()?;
 
             assert_eq!(target, bin);
         }
+
+        Ok(())
     }
     
+    #[test]
+    fn decode_response() -> Result<()> {
+        for (&bin, target) in zip(SAMPLE_BIN_RESPONSES, SAMPLE_RESPONSES.clone()) {
+            let response = Response::try_from(bin)?;
+
+            assert_eq!(target, response);
+            assert_eq!(bin, Vec::<u8>::try_from(response)?);
+        }
+
+        let response = Response::try_from(SAMPLE_BIN_RESPONSES[1])?;
+        assert_eq!(1, response.questions().len());
+        assert_eq!(1, response.answers().len());
+        assert_eq!(SAMPLE_ANSWER.clone(), response.answers()[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_pointer_into_rdata() -> Result<()> {
+        let bin = b"\x00\x01\x81\x00\x00\x01\x00\x02\x00\x00\x00\x00\
+                    \x03foo\x03bar\x00\x00\x05\x00\x01\
+                    \xc0\x0c\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x11\x03www\x07example\x03com\x00\
+                    \xc0\x29\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x01\x02\x03\x04";
+        let response = Response::try_from(&bin[..])?;
+
+        assert_eq!(&Name::from(vec!["foo", "bar"]), response.answers()[0].name());
+        assert_eq!(&Name::from(vec!["example", "com"]), response.answers()[1].name());
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_pointers_inside_rdata() -> Result<()> {
+        let bin = b"\x00\x01\x81\x80\x00\x01\x00\x02\x00\x00\x00\x00\
+                    \x07example\x03org\x00\x00\x0f\x00\x01\
+                    \xc0\x0c\x00\x05\x00\x01\x00\x00\x01\x2c\x00\x07\x04mail\xc0\x0c\
+                    \xc0\x0c\x00\x0f\x00\x01\x00\x00\x01\x2c\x00\x04\x00\x0a\xc0\x29";
+        let response = Response::try_from(&bin[..])?;
+        let mail = Name::from(vec!["mail", "example", "org"]);
+
+        assert_eq!(mail, response.answers()[0].record().cname_target()?);
+        assert_eq!(10, response.answers()[1].record().mx_preference()?);
+        assert_eq!(mail, response.answers()[1].record().mx_exchange()?);
+        assert_eq!(response, Response::try_from(&Vec::<u8>::try_from(response.clone())?[..])?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_compressed_mailbox_rdata() -> Result<()> {
+        let bin = b"\x00\x01\x81\x80\x00\x01\x00\x01\x00\x00\x00\x00\
+                    \x07example\x03org\x00\x00\x07\x00\x01\
+                    \xc0\x0c\x00\x07\x00\x01\x00\x00\x01\x2c\x00\x07\x04mail\xc0\x0c";
+        let response = Response::try_from(&bin[..])?;
+        let record = response.answers()[0].record();
+
+        assert_eq!(Name::from(vec!["mail", "example", "org"]), record.mb_name()?);
+        assert_eq!("300\tIN\tMB\tmail.example.org.", record.to_string());
+        assert_eq!("3600\tIN\tMG\tlist.example.org.", Record::from_mg(&"list.example.org".parse()?).with_ttl(3600).to_string());
+        assert_eq!("60\tIN\tMR\tnew.example.org.", Record::from_mr(&"new.example.org".parse()?).to_string());
+        assert!(record.mg_name().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn compress_rdata_names() -> Result<()> {
+        let name = Name::from(vec!["codecrafters", "io"]);
+        let target = Name::from(vec!["a", "codecrafters", "io"]);
+        let response = Response::builder()
+            .id(0x1234)
+            .questions(vec![Question::new(name.clone(), QType::RRType(RRType::NS), QClass::RRClass(RRClass::IN))])
+            .answers(vec![Answer::new(&name, &Record::new(RRType::NS, RRClass::IN, target.to_vec()).with_ttl(300))])
+            .response_code(ResponseCode::NoError)
+            .build();
+
+        let capture = b"\x12\x34\x80\x00\x00\x01\x00\x01\x00\x00\x00\x00\
+                        \x0ccodecrafters\x02io\x00\x00\x02\x00\x01\
+                        \xc0\x0c\x00\x02\x00\x01\x00\x00\x01\x2c\x00\x04\x01a\xc0\x0c";
+        let bytes: Vec<u8> = response.clone().try_into()?;
+
+        assert_eq!(&capture[..], &bytes[..]);
+        assert_eq!(response, Response::try_from(&bytes[..])?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compress_across_questions() -> Result<()> {
+        let question = |name: &str| -> Result<Question> {
+            Ok(Question::new(name.parse()?, QType::RRType(RRType::A), QClass::RRClass(RRClass::IN)))
+        };
+        let response = Response::builder()
+            .id(0x1234)
+            .questions(vec![question("a.example.com")?, question("b.example.com")?, question("c.b.example.com")?])
+            .response_code(ResponseCode::NoError)
+            .build();
+
+        let capture = b"\x12\x34\x80\x00\x00\x03\x00\x00\x00\x00\x00\x00\
+                        \x01a\x07example\x03com\x00\x00\x01\x00\x01\
+                        \x01b\xc0\x0e\x00\x01\x00\x01\
+                        \x01c\xc0\x1f\x00\x01\x00\x01";
+        let bytes: Vec<u8> = response.clone().try_into()?;
+
+        assert_eq!(&capture[..], &bytes[..]);
+        assert_eq!(response, Response::try_from(&bytes[..])?);
+
+        let mut query = bytes.clone();
+        query[2] = 0x00;
+        assert_eq!(response.questions(), Query::try_from(&query[..])?.questions());
+
+        Ok(())
+    }
+
+    #[test]
+    fn ns_target_compresses_against_apex() -> Result<()> {
+        let apex: Name = "example.com".parse()?;
+        let nameservers = ["ns1.example.com", "ns2.example.com"];
+        let response = Response::builder()
+            .id(0x1234)
+            .questions(vec![Question::new(apex.clone(), QType::RRType(RRType::NS), QClass::RRClass(RRClass::IN))])
+            .answers(nameservers.iter()
+                                .map(|ns| Ok(Answer::new(&apex, &Record::from_ns(&ns.parse()?).with_ttl(3600))))
+                                .collect::<Result<_>>()?)
+            .response_code(ResponseCode::NoError)
+            .build();
+        let bytes: Vec<u8> = response.clone().try_into()?;
+
+        assert_eq!(b"\x06\x03ns1\xc0\x0c", &bytes[40..47]);
+        assert_eq!(b"\x06\x03ns2\xc0\x0c", &bytes[58..]);
+
+        let decoded = Response::try_from(&bytes[..])?;
+        assert_eq!(response, decoded);
+        assert_eq!("ns2.example.com".parse::<Name>()?, decoded.answers()[1].record().ns_name()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn authority_and_additional_sections() -> Result<()> {
+        let apex: Name = "example.com".parse()?;
+        let nameserver: Name = "ns1.example.com".parse()?;
+        let soa = Record::from_soa(&nameserver, &"admin.example.com".parse()?, 1, 7200, 3600, 1209600, 300);
+        let response = Response::builder()
+            .id(0x1234)
+            .questions(vec![Question::new("missing.example.com".parse()?, QType::RRType(RRType::A), QClass::RRClass(RRClass::IN))])
+            .authority(vec![Answer::new(&apex, &soa)])
+            .additional(vec![Answer::new(&nameserver, &Record::from_ip_v4("192.0.2.1")?)])
+            .response_code(ResponseCode::NameError)
+            .build();
+        let bytes: Vec<u8> = response.clone().try_into()?;
+
+        assert_eq!([0, 1, 0, 0, 0, 1, 0, 1], bytes[4..12]);
+        assert_eq!(b"\xc0\x14", &bytes[37..39]);
+        assert_eq!(response, Response::try_from(&bytes[..])?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_names_fail_to_serialize() {
+        let question = Question::new(Name::new(vec!["www".into()], Some(12)), QType::RRType(RRType::A), QClass::RRClass(RRClass::IN));
+        let response = Response::builder()
+            .questions(vec![question.clone()])
+            .build();
+        let query = Query::builder()
+            .questions(vec![question])
+            .build();
+
+        assert!(Vec::<u8>::try_from(response).is_err());
+        assert!(query.to_vec().is_err());
+    }
+
+    #[test]
+    fn edns_query_round_trip() -> Result<()> {
+        let query = Query::builder()
+            .id(0xfdf0)
+            .recursion_desired(true)
+            .questions(vec![SAMPLE_QUESTION.clone()])
+            .edns(Edns::new(4096))
+            .build();
+        let bytes = query.to_vec()?;
+
+        assert_eq!([0, 1], bytes[10..12]);
+        assert!(bytes.ends_with(b"\x00\x00\x29\x10\x00\x00\x00\x00\x00\x00\x00"));
+        assert_eq!(query, Query::try_from(&bytes[..])?);
+        assert_eq!(Some(4096), Query::try_from(&bytes[..])?.edns().map(Edns::payload_size));
+
+        let edns = Edns::new(1232).set_dnssec_ok().option(EDNS_COOKIE, b"\x01\x02\x03\x04\x05\x06\x07\x08");
+        let bytes = Query::builder().id(0x4242).questions(vec![SAMPLE_QUESTION.clone()]).edns(edns.clone()).build().to_vec()?;
+        assert_eq!(Some(&edns), Query::try_from(&bytes[..])?.edns());
+        assert_eq!(None, Query::try_from(SAMPLE_BIN_QUERIES[0])?.edns());
+
+        Ok(())
+    }
+
+    #[test]
+    fn extended_response_code() -> Result<()> {
+        let response = Response::builder()
+            .id(0x1234)
+            .questions(vec![SAMPLE_QUESTION.clone()])
+            .additional(vec![Edns::new(1232).to_answer()])
+            .response_code(ResponseCode::BadVersion)
+            .build();
+        let bytes: Vec<u8> = response.clone().try_into()?;
+
+        assert_eq!(0, bytes[3] & 0x0f);
+        assert!(bytes.ends_with(b"\x00\x00\x29\x04\xd0\x01\x00\x00\x00\x00\x00"));
+        assert_eq!(response, Response::try_from(&bytes[..])?);
+
+        let without_opt = Response::builder().response_code(ResponseCode::BadVersion).build();
+        assert!(Vec::<u8>::try_from(without_opt).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn encode_into_matches_owned_encoding() -> Result<()> {
+        let mut buf = b"stale bytes".to_vec();
+
+        for response in SAMPLE_RESPONSES.iter() {
+            response.encode_into(&mut buf)?;
+            assert_eq!(Vec::<u8>::try_from(response.clone())?, buf);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn encode_into_reuses_buffer() -> Result<()> {
+        let response = SAMPLE_RESPONSES[1].clone();
+        let mut buf = Vec::with_capacity(512);
+
+        let (encoded, fresh) = allocations(|| Vec::<u8>::try_from(response.clone()));
+        encoded?;
+        let (cloned, cloning) = allocations(|| response.clone());
+        drop(cloned);
+        let (result, reused) = allocations(|| response.encode_into(&mut buf));
+        result?;
+
+        assert!(reused < fresh - cloning, "{reused} allocations reusing vs {} fresh", fresh - cloning);
+        assert_eq!(512, buf.capacity());
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_truncated_answer() {
+        let bin = SAMPLE_BIN_RESPONSES[1];
+
+        assert!(Response::try_from(&bin[..bin.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn borrow_questions_without_cloning() -> Result<()> {
+        let query = Query::try_from(SAMPLE_BIN_QUERIES[0])?;
+
+        assert!(allocations(|| query.questions().len()).1 > 0);
+        assert_eq!(0, allocations(|| query.questions_ref().len()).1);
+        assert_eq!(query.questions(), query.questions_ref());
+        assert_eq!(SAMPLE_QUERIES[0].questions(), query.into_questions());
+
+        Ok(())
+    }
+
+    #[test]
+    fn question_count_mismatch() -> Result<()> {
+        let mut bin = SAMPLE_BIN_QUERIES[0].to_vec();
+        bin[5] = 2;
+
+        let lenient = Query::try_from(&bin[..])?;
+        assert_eq!(ResponseCode::NoError, lenient.response_code());
+        assert_eq!(SAMPLE_QUERIES[0].questions(), lenient.questions());
+
+        let strict = Query::parse(&bin[..], true)?;
+        assert_eq!(ResponseCode::FormatError, strict.response_code());
+        assert_eq!(SAMPLE_QUERIES[0].questions(), strict.questions());
+
+        let mut trailing = SAMPLE_BIN_QUERIES[0].to_vec();
+        trailing.extend(b"\x00\x00");
+        assert_eq!(ResponseCode::NoError, Query::try_from(&trailing[..])?.response_code());
+        assert_eq!(ResponseCode::FormatError, Query::parse(&trailing[..], true)?.response_code());
+        assert_eq!(ResponseCode::NoError, Query::parse(SAMPLE_BIN_QUERIES[0], true)?.response_code());
+
+        Ok(())
+    }
+
+    #[test]
+    fn query_without_questions() -> Result<()> {
+        let bin = b"\xab\xcd\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+
+        let lenient = Query::try_from(&bin[..])?;
+        assert_eq!(ResponseCode::NoError, lenient.response_code());
+        assert!(lenient.questions_ref().is_empty());
+        assert_eq!(ResponseCode::FormatError, Query::parse(&bin[..], true)?.response_code());
+
+        Ok(())
+    }
+
+    #[test]
+    fn dnssec_header_bits() -> Result<()> {
+        let mut bin = SAMPLE_BIN_QUERIES[0].to_vec();
+        bin[3] = 0x30;
+
+        let query = Query::try_from(&bin[..])?;
+        assert!(query.authentic_data());
+        assert!(query.checking_disabled());
+        assert_eq!(SAMPLE_QUERIES[0].questions(), query.questions());
+        assert_eq!(bin, query.to_vec()?);
+
+        bin[3] = 0x40;
+        assert_eq!(ResponseCode::FormatError, Query::try_from(&bin[..])?.response_code());
+
+        let response: Vec<u8> = Response::builder()
+            .id(0xfdf0)
+            .recursion_desired(true)
+            .checking_disabled(true)
+            .questions(vec![SAMPLE_QUESTION.clone()])
+            .response_code(ResponseCode::NoError)
+            .build()
+            .try_into()?;
+        assert_eq!(0x10, response[3]);
+        assert!(Response::try_from(&response[..])?.checking_disabled);
+
+        Ok(())
+    }
+
     #[test]
     fn compressed_query() -> Result<()> {
         let bytes = b"\xce5\x01\x00\x00\x02\x00\x00\x00\x00\x00\x00\x03abc\x11longassdomainname\x03com\x00\x00\x01\x00\x01\x03def\xc0\x10\x00\x01\x00\x01".to_vec();
-        let _ = Query::try_from(&bytes[..])?;
+        let query = Query::try_from(&bytes[..])?;
+
+        assert_eq!(bytes, query.to_vec()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_query_from_code() -> Result<()> {
+        let query = Query::builder()
+            .id(0xfdf0)
+            .recursion_desired(true)
+            .questions(vec![Question::new(
+                vec!["codecrafters", "io"].into(),
+                QType::RRType(RRType::A),
+                QClass::RRClass(RRClass::IN),
+            )])
+            .build();
+
+        assert_eq!(SAMPLE_QUERIES[0], query);
+        assert_eq!(SAMPLE_BIN_QUERIES[0], query.to_vec()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn encode_query() -> Result<()> {
+        for (&bin, target) in zip(SAMPLE_BIN_QUERIES, SAMPLE_QUERIES.clone()) {
+            assert_eq!(bin, target.to_vec()?);
+            assert_eq!(target, Query::try_from(&Query::try_from(bin)?.to_vec()?[..])?);
+        }
+
+        Ok(())
+    }
+
+    fn random_message(rng: &mut StdRng) -> Vec<u8> {
+        let qdcount = rng.gen_range(0..3u16);
+        let ancount = rng.gen_range(0..3u16);
+        let mut message = vec![0, 0, 1, 0];
+        for count in [qdcount, ancount, 0, 0] {
+            message.extend(u16::to_be_bytes(count));
+        }
+
+        for k in 0..(qdcount + ancount) {
+            let labels = (0..rng.gen_range(1..4))
+                .map(|_| (0..rng.gen_range(1..10)).map(|_| rng.gen_range('a'..='z')).collect())
+                .collect::<Vec<String>>();
+            message.extend(Name::from(labels).to_vec());
+            message.extend(b"\x00\x01\x00\x01");
+            if k >= qdcount {
+                message.extend(b"\x00\x00\x00\x3c\x00\x04\x01\x02\x03\x04");
+            }
+        }
+
+        message
+    }
+
+    #[test]
+    fn validate_sample_messages() -> Result<()> {
+        for &bin in SAMPLE_BIN_QUERIES.iter().chain(SAMPLE_BIN_RESPONSES) {
+            validate_message_bytes(bin)?;
+        }
 
         Ok(())
     }
+
+    #[test]
+    fn validate_rejects_count_mismatch() {
+        let mut bin = SAMPLE_BIN_RESPONSES[1].to_vec();
+        bin[7] = 2;
+        assert!(validate_message_bytes(&bin).is_err());
+
+        bin[7] = 0;
+        assert!(validate_message_bytes(&bin).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_forward_pointer() {
+        let bin = b"\x00\x00\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\xc0\x0c\x00\x01\x00\x01";
+        assert!(validate_message_bytes(bin).is_err());
+    }
+
+    #[test]
+    fn random_messages_never_panic() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+
+        for _ in 0..500 {
+            let mut message = random_message(&mut rng);
+            assert!(validate_message_bytes(&message).is_ok());
+
+            for _ in 0..rng.gen_range(0..4) {
+                let k = rng.gen_range(0..message.len());
+                message[k] = rng.gen();
+            }
+            message.truncate(rng.gen_range(0..=message.len()));
+
+            let _ = validate_message_bytes(&message);
+            let _ = Query::try_from(&message[..]);
+        }
+    }
+
+    #[test]
+    fn random_bytes_never_panic() {
+        let mut rng = StdRng::seed_from_u64(0xf022);
+
+        for _ in 0..500 {
+            let mut bytes = vec![0; rng.gen_range(0..600)];
+            rng.fill(&mut bytes[..]);
+            if rng.gen() && bytes.len() > 4 {
+                bytes[4..6].copy_from_slice(&[0, 1]);
+            }
+
+            let _ = Query::try_from(&bytes[..]);
+            let _ = Response::try_from(&bytes[..]);
+            let _ = Question::try_from(&bytes[..]);
+            let _ = Answer::try_from(&bytes[..]);
+            let _ = Name::try_from(&bytes[..]);
+            let _ = validate_message_bytes(&bytes);
+        }
+    }
 }
@@ -0,0 +1,72 @@
+use std::{
+    fmt::Write,
+    net::IpAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::message::Response;
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => { let _ = write!(escaped, "\\u{:04x}", c as u32); },
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+pub fn json_line(timestamp: SystemTime, source: IpAddr, response: &Response, latency: Duration) -> String {
+    let timestamp = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let question = response.questions().first();
+    let qname = question.map_or("null".into(), |q| format!("\"{}\"", escape(&q.name().to_string())));
+    let qtype = question.map_or("null".into(), |q| u16::from(q.qtype().clone()).to_string());
+
+    format!(
+        "{{\"timestamp\":{}.{:06},\"source\":\"{}\",\"id\":{},\"qname\":{},\"qtype\":{},\"rcode\":{},\"answers\":{},\"latency_us\":{}}}",
+        timestamp.as_secs(),
+        timestamp.subsec_micros(),
+        source,
+        response.id(),
+        qname,
+        qtype,
+        u8::from(response.response_code()),
+        response.answers().len(),
+        latency.as_micros(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::{common::{QClass, QType, RRClass, RRType, ResponseCode}, message::Question};
+
+    #[test]
+    fn format_response_line() -> Result<()> {
+        let response = Response::builder()
+            .id(0xfdf0)
+            .questions(vec![Question::new(
+                vec!["we\"ird", "io"].into(),
+                QType::RRType(RRType::AAAA),
+                QClass::RRClass(RRClass::IN),
+            )])
+            .response_code(ResponseCode::NameError)
+            .build();
+        let timestamp = UNIX_EPOCH + Duration::from_micros(1_700_000_000_000_042);
+
+        assert_eq!(
+            "{\"timestamp\":1700000000.000042,\"source\":\"10.0.0.1\",\"id\":65008,\"qname\":\"we\\\"ird.io.\",\"qtype\":28,\"rcode\":3,\"answers\":0,\"latency_us\":150}",
+            json_line(timestamp, "10.0.0.1".parse()?, &response, Duration::from_micros(150)),
+        );
+        assert_eq!("a\\\\b\\u0001", escape("a\\b\u{1}"));
+
+        Ok(())
+    }
+}
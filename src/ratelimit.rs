@@ -0,0 +1,99 @@
+use std::{collections::HashMap, net::IpAddr, time::{Duration, Instant}};
+
+static PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    buckets: HashMap<IpAddr, Bucket>,
+    pruned: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        RateLimiter {
+            per_second: per_second as f64,
+            burst: burst as f64,
+            buckets: HashMap::new(),
+            pruned: Instant::now(),
+        }
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_second).min(self.burst)
+    }
+
+    pub fn allow(&mut self, source: IpAddr, now: Instant) -> bool {
+        if now.saturating_duration_since(self.pruned) >= PRUNE_INTERVAL {
+            self.prune(now);
+        }
+
+        let tokens = match self.buckets.get(&source) {
+            Some(bucket) => self.refilled(bucket, now),
+            None => self.burst,
+        };
+        let allowed = tokens >= 1.0;
+
+        self.buckets.insert(source, Bucket {
+            tokens: if allowed { tokens - 1.0 } else { tokens },
+            updated: now,
+        });
+
+        allowed
+    }
+
+    fn prune(&mut self, now: Instant) {
+        let idle = self.buckets
+                       .iter()
+                       .filter(|(_, bucket)| self.refilled(bucket, now) >= self.burst)
+                       .map(|(source, _)| *source)
+                       .collect::<Vec<_>>();
+        for source in idle {
+            self.buckets.remove(&source);
+        }
+
+        self.pruned = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let mut limiter = RateLimiter::new(2, 3);
+        let source = IpAddr::from([10, 0, 0, 1]);
+        let start = Instant::now();
+
+        assert_eq!(3, (0..10).filter(|_| limiter.allow(source, start)).count());
+        assert!(!limiter.allow(source, start + Duration::from_millis(100)));
+        assert!(limiter.allow(source, start + Duration::from_millis(600)));
+    }
+
+    #[test]
+    fn idle_buckets_are_pruned() {
+        let mut limiter = RateLimiter::new(1, 1);
+        let start = Instant::now();
+
+        limiter.allow(IpAddr::from([10, 0, 0, 1]), start);
+        limiter.allow(IpAddr::from([10, 0, 0, 2]), start + PRUNE_INTERVAL - Duration::from_millis(500));
+        assert_eq!(2, limiter.len());
+
+        limiter.allow(IpAddr::from([10, 0, 0, 3]), start + PRUNE_INTERVAL);
+        assert_eq!(2, limiter.len());
+    }
+}
@@ -1,20 +1,130 @@
-use std::{collections::HashMap, fmt::Debug, net::UdpSocket};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    fs::File,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    iter,
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, mpsc, Arc, Mutex, RwLock},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 
-use anyhow::{Result, bail};
-use once_cell::sync::Lazy;
+use anyhow::{Context, Result, bail};
+use rand::{Rng, RngCore, SeedableRng, rngs::StdRng};
 
-use crate::{common::{Name, Record}, message::{Answer, Query, Response}};
+use crate::{
+    acl::Cidr,
+    cache::{Cache, CacheKey, NegativeCache},
+    client::{Client, Timeout},
+    common::{Name, OpCode, QClass, QType, RRClass, RRType, Record, ResponseCode},
+    debug,
+    message::{Answer, Edns, Query, Question, Response},
+    querylog::json_line,
+    ratelimit::RateLimiter,
+    stats::{Stats, StatsSnapshot},
+    tcp::{read_framed, write_framed},
+    zone::{parse_hosts, parse_zone},
+};
 
 static DEFAULT_ADDRESS: &str = "127.0.0.1";
 static DEFAULT_PORT: u16 = 2053;
-static FAKE_RECORD: Lazy<Record> = Lazy::new(|| {
-    Record::from_ip_v4("8.8.8.8").unwrap()
-});
+static TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+static SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+static TCP_ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+static DEFAULT_UPSTREAM_TIMEOUT: Duration = Duration::from_secs(2);
+static DEFAULT_UPSTREAM_RETRIES: usize = 1;
+static DEFAULT_CACHE_CAPACITY: usize = 1024;
+static DEFAULT_NEGATIVE_CACHE_CAPACITY: usize = 256;
+static PREFETCH_THRESHOLD: f64 = 0.1;
+static MAX_CNAME_HOPS: usize = 8;
+static MAX_UDP_PAYLOAD: usize = 512;
+static EDNS_PAYLOAD_SIZE: u16 = 4096;
+static DEFAULT_VERSION_STRING: &str = "codecrafters-dns";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum EmptyPolicy {
+    #[default]
+    NoError,
+    Refused,
+    NameError,
+    ServerFailure,
+}
+
+impl From<EmptyPolicy> for ResponseCode {
+    fn from(value: EmptyPolicy) -> Self {
+        match value {
+            EmptyPolicy::NoError => ResponseCode::NoError,
+            EmptyPolicy::Refused => ResponseCode::Refused,
+            EmptyPolicy::NameError => ResponseCode::NameError,
+            EmptyPolicy::ServerFailure => ResponseCode::ServerFailure,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Sinkhole {
+    #[default]
+    NxDomain,
+    NullIp,
+    Refused,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mode {
+    Authoritative,
+    Forwarding,
+    #[default]
+    Hybrid,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UpstreamStrategy {
+    #[default]
+    Sequential,
+    Parallel,
+}
+
+struct IdSource(Box<dyn RngCore + Send>);
+
+impl Debug for IdSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IdSource")
+    }
+}
+
+struct LogSink(Box<dyn Write + Send>);
+
+impl Debug for LogSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LogSink")
+    }
+}
 
 #[derive(Debug)]
 pub struct ServerBuilder {
     address: String,
     port: u16,
+    empty_policy: EmptyPolicy,
+    mode: Mode,
+    tcp: bool,
+    strict: bool,
+    upstreams: Vec<SocketAddr>,
+    upstream_strategy: UpstreamStrategy,
+    upstream_timeout: Duration,
+    upstream_retries: usize,
+    cache_capacity: usize,
+    negative_cache_capacity: usize,
+    prefetch: bool,
+    id_source: Option<IdSource>,
+    allowed: Vec<String>,
+    rate_limit: Option<(u32, u32)>,
+    log_json: bool,
+    log_sink: Option<LogSink>,
+    sinkhole: Sinkhole,
+    views: Vec<(String, String)>,
+    version_string: String,
 }
 
 impl ServerBuilder {
@@ -28,27 +138,334 @@ impl ServerBuilder {
         self
     }
 
+    pub fn empty_response_policy(mut self, policy: EmptyPolicy) -> Self {
+        self.empty_policy = policy;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn set_tcp(mut self) -> Self {
+        self.tcp = true;
+        self
+    }
+
+    pub fn set_strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    pub fn upstream(mut self, upstream: SocketAddr) -> Self {
+        self.upstreams.push(upstream);
+        self
+    }
+
+    pub fn upstream_strategy(mut self, strategy: UpstreamStrategy) -> Self {
+        self.upstream_strategy = strategy;
+        self
+    }
+
+    pub fn upstream_timeout(mut self, timeout: Duration) -> Self {
+        self.upstream_timeout = timeout;
+        self
+    }
+
+    pub fn upstream_retries(mut self, retries: usize) -> Self {
+        self.upstream_retries = retries;
+        self
+    }
+
+    pub fn cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    pub fn negative_cache_capacity(mut self, capacity: usize) -> Self {
+        self.negative_cache_capacity = capacity;
+        self
+    }
+
+    pub fn prefetch(mut self, enabled: bool) -> Self {
+        self.prefetch = enabled;
+        self
+    }
+
+    pub fn id_source(mut self, rng: impl RngCore + Send + 'static) -> Self {
+        self.id_source = Some(IdSource(Box::new(rng)));
+        self
+    }
+
+    pub fn allow(mut self, cidr: &str) -> Self {
+        self.allowed.push(cidr.into());
+        self
+    }
+
+    pub fn rate_limit(mut self, per_second: u32, burst: u32) -> Self {
+        self.rate_limit = Some((per_second, burst));
+        self
+    }
+
+    pub fn version_string(mut self, version: &str) -> Self {
+        self.version_string = version.into();
+        self
+    }
+
+    pub fn view(mut self, name: &str, cidr: &str) -> Self {
+        self.views.push((name.into(), cidr.into()));
+        self
+    }
+
+    pub fn sinkhole_mode(mut self, mode: Sinkhole) -> Self {
+        self.sinkhole = mode;
+        self
+    }
+
+    pub fn log_json(mut self, enabled: bool) -> Self {
+        self.log_json = enabled;
+        self
+    }
+
+    pub fn log_sink(mut self, sink: impl Write + Send + 'static) -> Self {
+        self.log_sink = Some(LogSink(Box::new(sink)));
+        self
+    }
+
     pub fn build(self) -> Result<Server> {
+        let allowed = self.allowed
+                          .iter()
+                          .map(|cidr| cidr.parse().with_context(|| format!("invalid network {cidr}")))
+                          .collect::<Result<Vec<Cidr>>>()?;
+        let views = self.views
+                        .iter()
+                        .map(|(name, cidr)| Ok(View {
+                            name: name.clone(),
+                            network: cidr.parse().with_context(|| format!("invalid network {cidr} for view {name}"))?,
+                            records: RecordStore::new(),
+                        }))
+                        .collect::<Result<Vec<View>>>()?;
+        let socket = UdpSocket::bind((self.address.as_str(), self.port))
+            .with_context(|| format!("failed to bind {}:{}", self.address, self.port))?;
+        let tcp_listener = if self.tcp {
+            let local = socket.local_addr()?;
+            Some(TcpListener::bind(local).with_context(|| format!("failed to bind {local} over TCP"))?)
+        } else {
+            None
+        };
+
         Ok(Server {
-            socket: UdpSocket::bind((self.address.as_str(), self.port)).expect("Failed to bind to address"),
+            socket,
+            tcp_listener,
             address: self.address,
             port: self.port,
-            records: HashMap::new(),
+            records: RwLock::new(Arc::new(Store::default())),
+            sources: vec![],
+            rotation: AtomicUsize::new(0),
+            empty_policy: self.empty_policy,
+            mode: self.mode,
+            strict: self.strict,
+            upstreams: self.upstreams,
+            upstream_strategy: self.upstream_strategy,
+            upstream_client: Mutex::new(Client::default().timeout(self.upstream_timeout).retries(self.upstream_retries)),
+            cache: Arc::new(Mutex::new(Cache::new(self.cache_capacity))),
+            negative_cache: Mutex::new(NegativeCache::new(self.negative_cache_capacity)),
+            prefetch: self.prefetch,
+            ids: Mutex::new(self.id_source.unwrap_or_else(|| IdSource(Box::new(StdRng::from_entropy())))),
+            allowed,
+            limiter: self.rate_limit.map(|(per_second, burst)| Mutex::new(RateLimiter::new(per_second, burst))),
+            stats: Stats::default(),
+            buffers: Mutex::new((vec![0; EDNS_PAYLOAD_SIZE as usize], Vec::with_capacity(MAX_UDP_PAYLOAD))),
+            blocklist: HashSet::new(),
+            sinkhole: self.sinkhole,
+            views,
+            zones: vec![],
+            version_string: self.version_string,
+            query_log: self.log_json.then(|| Mutex::new(self.log_sink.unwrap_or_else(|| LogSink(Box::new(std::io::stderr()))))),
         })
     }
 }
 
 impl Default for ServerBuilder {
     fn default() -> Self {
-        ServerBuilder { address: DEFAULT_ADDRESS.into(), port: DEFAULT_PORT }
+        ServerBuilder {
+            address: DEFAULT_ADDRESS.into(),
+            port: DEFAULT_PORT,
+            empty_policy: EmptyPolicy::default(),
+            mode: Mode::default(),
+            tcp: false,
+            strict: false,
+            upstreams: vec![],
+            upstream_strategy: UpstreamStrategy::default(),
+            upstream_timeout: DEFAULT_UPSTREAM_TIMEOUT,
+            upstream_retries: DEFAULT_UPSTREAM_RETRIES,
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            negative_cache_capacity: DEFAULT_NEGATIVE_CACHE_CAPACITY,
+            prefetch: false,
+            id_source: None,
+            allowed: vec![],
+            rate_limit: None,
+            log_json: false,
+            log_sink: None,
+            sinkhole: Sinkhole::default(),
+            views: vec![],
+            version_string: DEFAULT_VERSION_STRING.into(),
+        }
+    }
+}
+
+type Resolution = (Vec<Answer>, Vec<Answer>, ResponseCode, bool);
+type RecordStore = HashMap<Name, Vec<Record>>;
+
+/// Loaded records, along with the zones declared by SOA records in zone files.
+/// Both are rebuilt and swapped together on reload.
+#[derive(Clone, Debug, Default)]
+struct Store {
+    records: RecordStore,
+    zones: Vec<Name>,
+}
+
+#[derive(Debug)]
+enum Source {
+    Record(Name, Record),
+    Zone(PathBuf),
+    Hosts(PathBuf),
+}
+
+impl Source {
+    fn read(&self) -> Result<Vec<(Name, Record)>> {
+        let open = |path: &Path| {
+            File::open(path).map(BufReader::new).with_context(|| format!("failed to open {}", path.display()))
+        };
+
+        match self {
+            Source::Record(name, record) => Ok(vec![(name.clone(), record.clone())]),
+            Source::Zone(path) => parse_zone(open(path)?),
+            Source::Hosts(path) => parse_hosts(open(path)?),
+        }
+    }
+
+    fn load_into(&self, store: &mut Store) -> Result<()> {
+        let records = self.read()?;
+        if let Source::Zone(_) = self {
+            soa_owners(&records).for_each(|apex| insert_zone(&mut store.zones, apex));
+        }
+
+        for (name, record) in records {
+            insert_into(&mut store.records, name, record);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct View {
+    name: String,
+    network: Cidr,
+    records: RecordStore,
+}
+
+struct Records<'a> {
+    store: Arc<Store>,
+    view: Option<&'a RecordStore>,
+}
+
+impl Records<'_> {
+    fn get(&self, name: &Name) -> Option<&Vec<Record>> {
+        self.view.and_then(|view| view.get(name)).or_else(|| self.store.records.get(name))
+    }
+
+    fn contains_key(&self, name: &Name) -> bool {
+        self.get(name).is_some()
+    }
+}
+
+fn exchange_upstream(client: &Client, upstreams: &[SocketAddr], strategy: UpstreamStrategy, query: &Query) -> Result<Response> {
+    let results: Box<dyn Iterator<Item = (SocketAddr, Result<Response>)>> = match strategy {
+        UpstreamStrategy::Sequential => Box::new(upstreams.iter().map(|&upstream| (upstream, client.exchange(upstream, query)))),
+        UpstreamStrategy::Parallel => {
+            let (sender, receiver) = mpsc::channel();
+            for &upstream in upstreams {
+                let (sender, client, query) = (sender.clone(), client.clone(), query.clone());
+                thread::spawn(move || {
+                    let _ = sender.send((upstream, client.exchange(upstream, &query)));
+                });
+            }
+            Box::new(receiver.into_iter())
+        },
+    };
+    let mut failure = None;
+
+    for (upstream, result) in results {
+        match result {
+            Ok(response) if response.response_code() == ResponseCode::ServerFailure => failure = Some(Ok(response)),
+            Ok(response) => return Ok(response),
+            Err(err) => {
+                eprintln!("Upstream {upstream} failed: {err}");
+                failure = Some(Err(err));
+            },
+        }
+    }
+
+    failure.unwrap_or_else(|| Err(anyhow::anyhow!("No upstream configured")))
+}
+
+fn insert_into(store: &mut RecordStore, name: Name, record: Record) {
+    store.entry(name.to_lowercase())
+         .or_default()
+         .push(record);
+}
+
+fn insert_zone(zones: &mut Vec<Name>, apex: &Name) {
+    let apex = apex.to_lowercase();
+    if !zones.contains(&apex) {
+        zones.push(apex);
     }
 }
 
+fn soa_owners(records: &[(Name, Record)]) -> impl Iterator<Item = &Name> {
+    records.iter().filter(|(_, record)| *record.rrtype() == RRType::SOA).map(|(name, _)| name)
+}
+
+/// A processed query, along with the EDNS parameters the client sent and the
+/// UDP payload size the response was allowed to use.
+#[derive(Debug)]
+pub struct Exchange {
+    pub response: Response,
+    pub edns: Option<Edns>,
+    pub payload_size: usize,
+}
+
 pub struct Server {
     address: String,
     port: u16,
     socket: UdpSocket,
-    records: HashMap<Name, Record>,
+    tcp_listener: Option<TcpListener>,
+    records: RwLock<Arc<Store>>,
+    sources: Vec<Source>,
+    rotation: AtomicUsize,
+    empty_policy: EmptyPolicy,
+    mode: Mode,
+    strict: bool,
+    upstreams: Vec<SocketAddr>,
+    upstream_strategy: UpstreamStrategy,
+    upstream_client: Mutex<Client>,
+    cache: Arc<Mutex<Cache>>,
+    negative_cache: Mutex<NegativeCache>,
+    prefetch: bool,
+    ids: Mutex<IdSource>,
+    allowed: Vec<Cidr>,
+    limiter: Option<Mutex<RateLimiter>>,
+    stats: Stats,
+    buffers: Mutex<(Vec<u8>, Vec<u8>)>,
+    blocklist: HashSet<Name>,
+    sinkhole: Sinkhole,
+    views: Vec<View>,
+    zones: Vec<Name>,
+    version_string: String,
+    query_log: Option<Mutex<LogSink>>,
 }
 
 impl Server {
@@ -56,58 +473,2199 @@ impl Server {
         ServerBuilder::default().build()
     }
 
-    fn process_query(&self, query: Query) -> Response {
-        let answers = query.questions()
-                       .iter()
-//                       .flat_map(|q| self.lookup(q.name()).map(|r| (q, r)))
-//                       .map(|(q, r)| Answer::new(q.name(), r, 60))
-                       .map(|q| { eprintln!("Building answer from {q:?}"); Answer::new(q.name(), &FAKE_RECORD, 60) } )
-                       .collect::<Vec<_>>();
-        let response = Response::builder()
+    fn forward(&self, question: &Question) -> Result<Resolution> {
+        let key = (question.name().to_lowercase(), question.qtype().clone(), question.qclass().clone());
+        let cached = {
+            let mut cache = self.cache.lock().unwrap();
+            cache.get(&key).map(|answers| (answers, self.prefetch && cache.claim_refresh(&key, PREFETCH_THRESHOLD)))
+        };
+        if let Some((answers, refresh)) = cached {
+            self.stats.cache_hit();
+            if refresh {
+                self.refresh(question, key);
+            }
+            return Ok((answers, vec![], ResponseCode::NoError, false));
+        }
+        if let Some((response_code, soa)) = self.negative_cache.lock().unwrap().get(&key) {
+            self.stats.cache_hit();
+            return Ok((vec![], vec![soa], response_code, false));
+        }
+        self.stats.cache_miss();
+
+        let query = Query::builder()
+            .id(self.ids.lock().unwrap().0.gen())
+            .recursion_desired(true)
+            .questions(vec![question.clone()])
+            .build();
+
+        self.stats.forward();
+        let client = self.upstream_client.lock().unwrap().clone();
+        let response = exchange_upstream(&client, &self.upstreams, self.upstream_strategy, &query)
+            .inspect_err(|err| if err.is::<Timeout>() { self.stats.upstream_timeout() })?;
+        let answers = response.answers().to_vec();
+        let soa = response.authority().iter().find(|a| *a.record().rrtype() == RRType::SOA);
+        let authority = soa.into_iter().cloned().collect();
+
+        match (response.response_code(), soa) {
+            (ResponseCode::NameError, Some(soa)) => self.negative_cache.lock().unwrap().insert(key, ResponseCode::NameError, soa),
+            (ResponseCode::NoError, Some(soa)) if answers.is_empty() => self.negative_cache.lock().unwrap().insert(key, ResponseCode::NoError, soa),
+            (ResponseCode::NoError, _) => self.cache.lock().unwrap().insert(key, answers.clone()),
+            _ => {},
+        }
+
+        Ok((answers, authority, response.response_code(), false))
+    }
+
+    fn refresh(&self, question: &Question, key: CacheKey) {
+        let query = Query::builder()
+            .id(self.ids.lock().unwrap().0.gen())
+            .recursion_desired(true)
+            .questions(vec![question.clone()])
+            .build();
+        let cache = self.cache.clone();
+        let upstreams = self.upstreams.clone();
+        let strategy = self.upstream_strategy;
+        let client = self.upstream_client.lock().unwrap().clone();
+
+        self.stats.forward();
+        thread::spawn(move || match exchange_upstream(&client, &upstreams, strategy, &query) {
+            Ok(response) if response.response_code() == ResponseCode::NoError => {
+                cache.lock().unwrap().insert(key, response.answers().to_vec());
+            },
+            Ok(response) => eprintln!("Prefetch of {} failed with {}", key.0, response.response_code()),
+            Err(err) => eprintln!("Failed to prefetch {}: {err}", key.0),
+        });
+    }
+
+    /// Resolves the given names through the upstreams so their answers are cached before
+    /// serving. Names are resolved concurrently and failures are only reported.
+    pub fn prewarm(&self, names: Vec<(&str, QType)>) {
+        if !self.forwards() {
+            return;
+        }
+
+        thread::scope(|s| {
+            for (name, qtype) in names {
+                s.spawn(move || {
+                    let question = name.parse().map(|qname| Question::new(qname, qtype, QClass::RRClass(RRClass::IN)));
+                    if let Err(err) = question.and_then(|question| self.forward(&question)) {
+                        eprintln!("Failed to prewarm {name}: {err}");
+                    }
+                });
+            }
+        });
+    }
+
+    fn zone_soa(&self, records: &Records, name: &Name) -> Option<Answer> {
+        iter::successors(Some(name.to_lowercase()), Name::parent).find_map(|apex| {
+            let soa = records.get(&apex)?.iter().find(|r| *r.rrtype() == RRType::SOA)?;
+            let ttl = soa.soa().map_or(soa.ttl(), |fields| soa.ttl().min(fields.minimum));
+
+            Some(Answer::new(&apex, &soa.clone().with_ttl(ttl)))
+        })
+    }
+
+    fn forwards(&self) -> bool {
+        self.mode != Mode::Authoritative && !self.upstreams.is_empty()
+    }
+
+    fn zones<'a>(&'a self, records: &'a Records) -> impl Iterator<Item = &'a Name> {
+        self.zones.iter().chain(&records.store.zones)
+    }
+
+    fn zone_for<'a>(&'a self, records: &'a Records, name: &Name) -> Option<&'a Name> {
+        iter::successors(Some(name.to_lowercase()), Name::parent)
+            .find_map(|apex| self.zones(records).find(|zone| **zone == apex))
+    }
+
+    fn chase(&self, records: &Records, question: &Question) -> Result<Vec<Answer>> {
+        let mut answers = vec![];
+        let mut name = question.name().clone();
+
+        for _ in 0..=MAX_CNAME_HOPS {
+            let matching = self.lookup_in(records, &name, question.qtype());
+            if !matching.is_empty() {
+                answers.extend(matching.iter().map(|r| Answer::new(&name, r)));
+                break;
+            }
+
+            let cnames = self.lookup_in(records, &name, &QType::RRType(RRType::CNAME));
+            let Some(cname) = cnames.first() else {
+                break;
+            };
+            let Ok(target) = cname.cname_target() else {
+                break;
+            };
+
+            answers.push(Answer::new(&name, cname));
+            name = target;
+            if self.owner(records, &name).is_none() {
+                if self.forwards() {
+                    let target = Question::new(name, question.qtype().clone(), question.qclass().clone());
+                    answers.extend(self.forward(&target)?.0);
+                }
+                break;
+            }
+        }
+
+        Ok(answers)
+    }
+
+    fn is_blocked(&self, name: &Name) -> bool {
+        if self.blocklist.is_empty() {
+            return false;
+        }
+
+        iter::successors(Some(name.to_lowercase()), Name::parent).any(|suffix| self.blocklist.contains(&suffix))
+    }
+
+    fn sinkhole(&self, question: &Question) -> Result<Resolution> {
+        let answers = match self.sinkhole {
+            Sinkhole::NxDomain => return Ok((vec![], vec![], ResponseCode::NameError, false)),
+            Sinkhole::Refused => return Ok((vec![], vec![], ResponseCode::Refused, false)),
+            Sinkhole::NullIp => [Record::from_ip_v4("0.0.0.0")?, Record::from_ip_v6("::")?]
+                                    .iter()
+                                    .filter(|r| question.qtype().matches(r.rrtype()))
+                                    .map(|r| Answer::new(question.name(), r))
+                                    .collect(),
+        };
+
+        Ok((answers, vec![], ResponseCode::NoError, false))
+    }
+
+    fn chaos(&self, question: &Question) -> Result<Option<Vec<Answer>>> {
+        let name = question.name().to_lowercase().to_string();
+        if *question.qclass() != QClass::RRClass(RRClass::CH) || !["version.bind.", "hostname.bind."].contains(&name.as_str()) {
+            return Ok(None);
+        }
+
+        let txt = Record::from_txt(&[&self.version_string])?;
+        let record = Record::new(RRType::TXT, RRClass::CH, txt.data().clone()).with_ttl(0);
+
+        if !question.qtype().matches(&RRType::TXT) {
+            return Ok(Some(vec![]));
+        }
+
+        Ok(Some(vec![Answer::new(question.name(), &record)]))
+    }
+
+    fn resolve(&self, records: &Records, question: &Question) -> Result<Resolution> {
+        if let Some(answers) = self.chaos(question)? {
+            return Ok((answers, vec![], ResponseCode::NoError, false));
+        }
+
+        if self.is_blocked(question.name()) {
+            return self.sinkhole(question);
+        }
+
+        if self.mode == Mode::Forwarding {
+            return match self.forwards() {
+                true => self.forward(question),
+                false => Ok((vec![], vec![], ResponseCode::Refused, false)),
+            };
+        }
+
+        let authoritative = self.mode == Mode::Authoritative;
+        let in_zone = self.zone_for(records, question.name()).is_some();
+        if self.owner(records, question.name()).is_some() {
+            return Ok((self.chase(records, question)?, vec![], ResponseCode::NoError, in_zone || authoritative));
+        }
+
+        if let Some(soa) = self.zone_soa(records, question.name()) {
+            return Ok((vec![], vec![soa], ResponseCode::NameError, true));
+        }
+
+        if in_zone {
+            return Ok((vec![], vec![], ResponseCode::NameError, true));
+        }
+
+        if self.forwards() {
+            return self.forward(question);
+        }
+
+        let has_zones = self.zones(records).next().is_some();
+        let response_code = if has_zones {
+            ResponseCode::Refused
+        } else if authoritative {
+            ResponseCode::NameError
+        } else {
+            self.empty_policy.clone().into()
+        };
+
+        Ok((vec![], vec![], response_code, authoritative && !has_zones))
+    }
+
+    pub fn process_query(&self, query: Query, source: Option<IpAddr>) -> Response {
+        let records = self.records_for(source);
+        let mut answers = vec![];
+        let mut authority = vec![];
+        let mut authoritative = !query.questions_ref().is_empty();
+        let mut response_code = match query.opcode() {
+            OpCode::Query => query.response_code(),
+            _ => ResponseCode::NotImplemented,
+        };
+        if query.edns().is_some_and(|edns| edns.version() > 0) {
+            response_code = ResponseCode::BadVersion;
+        }
+
+        if response_code == ResponseCode::NoError {
+            for question in query.questions_ref() {
+                match self.resolve(&records, question) {
+                    Ok((resolved, soa, rcode, local)) => {
+                        answers.extend(resolved);
+                        authority.extend(soa);
+                        authoritative &= local;
+                        if rcode != ResponseCode::NoError {
+                            response_code = rcode;
+                        }
+                    },
+                    Err(err) => {
+                        eprintln!("Failed to resolve {}: {err}", question.name());
+                        response_code = ResponseCode::ServerFailure;
+                    },
+                }
+            }
+        }
+
+        let additional = match query.edns() {
+            Some(_) => vec![Edns::new(EDNS_PAYLOAD_SIZE).to_answer()],
+            None => vec![],
+        };
+        authoritative &= matches!(response_code, ResponseCode::NoError | ResponseCode::NameError);
+
+        self.stats.query();
+        self.stats.response(&response_code);
+        for answer in &answers {
+            self.stats.answer(answer.record().rrtype());
+        }
+
+        let mut response = Response::builder()
             .id(query.id())
             .opcode(query.opcode())
             .recursion_desired(query.recursion_desired())
-            .questions(query.questions())
+            .checking_disabled(query.checking_disabled())
             .answers(answers)
-            .response_code(query.response_code());
+            .authority(authority)
+            .additional(additional)
+            .response_code(response_code)
+            .questions(query.into_questions());
+        if authoritative {
+            response = response.set_authoritative_answer();
+        }
+        if self.forwards() {
+            response = response.set_recursion_available();
+        }
 
         response.build()
     }
 
-    pub fn serve(&mut self) -> Result<()> {
-        let mut buf = [0; 512];
+    pub fn process_message_bytes(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        self.process_query(Query::parse(bytes, self.strict)?, None).try_into()
+    }
+
+    pub fn respond_to(&self, bytes: &[u8]) -> Vec<u8> {
+        self.process_message_bytes(bytes).unwrap_or_else(|err| {
+            eprintln!("Failed to answer query: {err}");
+            vec![]
+        })
+    }
+
+    pub fn exchange(&self, bytes: &[u8], source: Option<IpAddr>) -> Result<Exchange> {
+        let query = Query::parse(bytes, self.strict)?;
+        let edns = query.edns().cloned();
+        let payload_size = edns.as_ref().map_or(MAX_UDP_PAYLOAD, |edns| {
+            (edns.payload_size() as usize).clamp(MAX_UDP_PAYLOAD, EDNS_PAYLOAD_SIZE as usize)
+        });
+
+        Ok(Exchange {
+            response: self.process_query(query, source),
+            edns,
+            payload_size,
+        })
+    }
+
+    pub fn process_datagram_bytes(&self, bytes: &[u8], source: IpAddr) -> Result<Vec<u8>> {
+        let mut buf = vec![];
+        self.process_datagram_into(bytes, source, &mut buf)?;
+
+        Ok(buf)
+    }
+
+    fn process_datagram_into(&self, bytes: &[u8], source: IpAddr, buf: &mut Vec<u8>) -> Result<Response> {
+        let Exchange { response, payload_size, .. } = self.exchange(bytes, Some(source))?;
+        response.encode_into(buf)?;
+
+        if buf.len() > payload_size {
+            let truncated = response.truncated();
+            truncated.encode_into(buf)?;
+            return Ok(truncated);
+        }
+
+        Ok(response)
+    }
+
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    pub fn is_allowed(&self, address: &IpAddr) -> bool {
+        self.allowed.is_empty() || self.allowed.iter().any(|cidr| cidr.contains(address))
+    }
+
+    fn within_rate(&self, source: IpAddr) -> bool {
+        match &self.limiter {
+            Some(limiter) => limiter.lock().unwrap().allow(source, Instant::now()),
+            None => true,
+        }
+    }
+
+    fn refuse(&self, bytes: &[u8]) -> Result<Response> {
+        let query = Query::parse(bytes, self.strict)?;
+        self.stats.query();
+        self.stats.response(&ResponseCode::Refused);
+
+        Ok(Response::builder()
+            .id(query.id())
+            .opcode(query.opcode())
+            .recursion_desired(query.recursion_desired())
+            .questions(query.into_questions())
+            .response_code(ResponseCode::Refused)
+            .build())
+    }
+
+    fn transfer(&self, query: &Query) -> Vec<Response> {
+        let questions = query.questions();
+        let reply = |questions: Vec<Question>, answers: Vec<Answer>, response_code: ResponseCode| {
+            Response::builder()
+                .id(query.id())
+                .opcode(query.opcode())
+                .set_authoritative_answer()
+                .questions(questions)
+                .answers(answers)
+                .response_code(response_code)
+                .build()
+        };
+
+        let apex = questions[0].name().to_lowercase();
+        let store = self.records();
+        let records = &store.records;
+        let Some(soa) = records.get(&apex).and_then(|rs| rs.iter().find(|r| *r.rrtype() == RRType::SOA)) else {
+            return vec![reply(questions, vec![], ResponseCode::Refused)];
+        };
+        let soa = Answer::new(&apex, soa);
 
-        match self.socket.recv_from(&mut buf) {
-            Ok((size, source)) => {
-                println!("Received {} bytes from {}", size, source);
-                let response = self.process_query(Query::try_from(&buf[..size])?);
-                let resp_vec: Vec<u8> = response.into();
-                self.socket
-                    .send_to(&resp_vec, source)
-                    .expect("Failed to send response");
+        let mut names = records
+                            .keys()
+                            .filter(|name| name.is_subdomain_of(&apex))
+                            .collect::<Vec<_>>();
+        names.sort_by_key(|name| (name.labels().len(), name.to_string()));
+
+        let mut responses = vec![reply(questions, vec![soa.clone()], ResponseCode::NoError)];
+        for name in names {
+            for record in &records[name] {
+                if *name != apex || *record.rrtype() != RRType::SOA {
+                    responses.push(reply(vec![], vec![Answer::new(name, record)], ResponseCode::NoError));
+                }
             }
-            Err(e) => {
-                bail!("Error receiving data: {}", e);
+        }
+        responses.push(reply(vec![], vec![soa], ResponseCode::NoError));
+
+        responses
+    }
+
+    pub fn handle_tcp_stream(&self, reader: &mut impl Read, writer: &mut impl Write, source: Option<IpAddr>) -> Result<()> {
+        while let Some(message) = read_framed(reader)? {
+            let query = Query::parse(&message[..], self.strict)?;
+            let is_transfer = query.response_code() == ResponseCode::NoError
+                && query.questions_ref().first().is_some_and(|q| *q.qtype() == QType::AXFR);
+
+            if is_transfer {
+                for response in self.transfer(&query) {
+                    write_framed(writer, &Vec::<u8>::try_from(response)?)?;
+                }
+            } else {
+                write_framed(writer, &Vec::<u8>::try_from(self.process_query(query, source))?)?;
             }
         }
 
         Ok(())
     }
 
-    pub fn add_record(&mut self, name: &str, record: Record) {
-        self.records.insert(
-            Name::from(name.split('.').collect::<Vec<_>>()),
-            record
-            );
+    pub fn serve_tcp(&self) -> Result<()> {
+        let Some(listener) = &self.tcp_listener else {
+            bail!("TCP transport is not enabled")
+        };
+
+        let (stream, source) = listener.accept()?;
+        self.serve_tcp_connection(stream, source)
     }
 
-    pub fn lookup(&self, name: &Name) -> Option<&Record> {
-        self.records.get(name)
+    fn serve_tcp_connection(&self, mut stream: TcpStream, source: SocketAddr) -> Result<()> {
+        if !self.is_allowed(&source.ip()) {
+            bail!("Refused TCP connection from {source}");
+        }
+
+        debug::trace(format_args!("Accepted TCP connection from {source}"));
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(TCP_IDLE_TIMEOUT))?;
+        self.handle_tcp_stream(&mut stream.try_clone()?, &mut stream, Some(source.ip()))
     }
-}
 
-impl Debug for Server {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Server<{}, {}>", self.address, self.port)
+    /// Accepts TCP connections until `shutdown` is set, serving each one on its own
+    /// thread. Connections beyond `max_connections` are closed right away.
+    pub fn serve_tcp_forever(&self, max_connections: usize, shutdown: &AtomicBool) -> Result<()> {
+        let Some(listener) = &self.tcp_listener else {
+            bail!("TCP transport is not enabled")
+        };
+        listener.set_nonblocking(true)?;
+        let active = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            while !shutdown.load(Ordering::SeqCst) {
+                let (stream, source) = match listener.accept() {
+                    Ok(accepted) => accepted,
+                    Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {
+                        thread::sleep(TCP_ACCEPT_POLL_INTERVAL);
+                        continue;
+                    },
+                    Err(e) => {
+                        eprintln!("Error accepting TCP connection: {}", e);
+                        continue;
+                    },
+                };
+                if active.fetch_add(1, Ordering::SeqCst) >= max_connections.max(1) {
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    eprintln!("Dropping TCP connection from {source}: too many connections");
+                    continue;
+                }
+
+                let active = &active;
+                s.spawn(move || {
+                    if let Err(err) = self.serve_tcp_connection(stream, source) {
+                        eprintln!("{err}");
+                    }
+                    active.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    fn answer_datagram(&self, bytes: &[u8], source: SocketAddr, scratch: &mut Vec<u8>) -> Result<()> {
+        if !self.within_rate(source.ip()) {
+            eprintln!("Dropping query from {source}: rate limit exceeded");
+            return Ok(());
+        }
+
+        debug::trace(format_args!("Received {} bytes from {source}", bytes.len()));
+        let started = Instant::now();
+        let response = if self.is_allowed(&source.ip()) {
+            self.process_datagram_into(bytes, source.ip(), scratch)?
+        } else {
+            let response = self.refuse(bytes)?;
+            response.encode_into(scratch)?;
+            response
+        };
+        self.log_query(source.ip(), &response, started.elapsed());
+        self.socket
+            .send_to(scratch, source)
+            .with_context(|| format!("Failed to send response to {source}"))?;
+
+        Ok(())
+    }
+
+    fn log_query(&self, source: IpAddr, response: &Response, latency: Duration) {
+        let Some(sink) = &self.query_log else {
+            return;
+        };
+
+        let line = json_line(SystemTime::now(), source, response, latency);
+        if let Err(err) = writeln!(sink.lock().unwrap().0, "{line}") {
+            eprintln!("Failed to write query log: {err}");
+        }
+    }
+
+    pub fn serve(&self) -> Result<()> {
+        let mut buffers = self.buffers.lock().unwrap();
+        let (receive, send) = &mut *buffers;
+
+        match self.socket.recv_from(receive) {
+            Ok((size, source)) => self.answer_datagram(&receive[..size], source, send),
+            Err(e) => {
+                bail!("Error receiving data: {}", e);
+            }
+        }
+    }
+
+    pub fn serve_forever(&self, num_workers: usize, shutdown: &AtomicBool) -> Result<()> {
+        let (sender, receiver) = mpsc::channel::<(Vec<u8>, SocketAddr)>();
+        let (recycler, spare) = mpsc::channel::<Vec<u8>>();
+        let receiver = Mutex::new(receiver);
+        self.socket.set_read_timeout(Some(SHUTDOWN_POLL_INTERVAL))?;
+
+        let result = thread::scope(|s| {
+            for _ in 0..num_workers.max(1) {
+                let recycler = recycler.clone();
+                let receiver = &receiver;
+                s.spawn(move || {
+                    let mut scratch = Vec::with_capacity(MAX_UDP_PAYLOAD);
+                    loop {
+                        let Ok((bytes, source)) = receiver.lock().unwrap().recv() else {
+                            break;
+                        };
+                        if let Err(err) = self.answer_datagram(&bytes, source, &mut scratch) {
+                            eprintln!("{err}");
+                        }
+                        let _ = recycler.send(bytes);
+                    }
+                });
+            }
+
+            let mut buf = [0; EDNS_PAYLOAD_SIZE as usize];
+            while !shutdown.load(Ordering::SeqCst) {
+                match self.socket.recv_from(&mut buf) {
+                    Ok((size, source)) => {
+                        let mut datagram = spare.try_recv().unwrap_or_default();
+                        datagram.clear();
+                        datagram.extend_from_slice(&buf[..size]);
+                        sender.send((datagram, source))?
+                    },
+                    Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+                    Err(e) => eprintln!("Error receiving data: {}", e),
+                }
+            }
+
+            drop(sender);
+            Ok(())
+        });
+
+        self.socket.set_read_timeout(None)?;
+        result
+    }
+
+    pub fn add_record(&mut self, name: &str, record: Record) -> Result<()> {
+        self.insert_record(name.parse()?, record);
+
+        Ok(())
+    }
+
+    /// Changes the per-attempt upstream timeout, also while the server is running.
+    pub fn set_forward_timeout(&self, timeout: Duration) {
+        let mut client = self.upstream_client.lock().unwrap();
+        *client = client.clone().timeout(timeout);
+    }
+
+    pub fn add_zone(&mut self, apex: Name) {
+        insert_zone(&mut self.zones, &apex);
+    }
+
+    pub fn add_blocklist<'a>(&mut self, names: impl Iterator<Item = &'a str>) -> Result<()> {
+        for name in names {
+            let name: Name = name.parse()?;
+            self.blocklist.insert(name.to_lowercase());
+        }
+
+        Ok(())
+    }
+
+    pub fn add_record_to_view(&mut self, view: &str, name: &str, record: Record) -> Result<()> {
+        let Some(view) = self.views.iter_mut().find(|v| v.name == view) else {
+            bail!("Unknown view {view}");
+        };
+        insert_into(&mut view.records, name.parse()?, record);
+
+        Ok(())
+    }
+
+    fn records(&self) -> Arc<Store> {
+        self.records.read().unwrap().clone()
+    }
+
+    fn records_for(&self, source: Option<IpAddr>) -> Records<'_> {
+        let view = source.and_then(|ip| self.views.iter().find(|view| view.network.contains(&ip)));
+
+        Records {
+            store: self.records(),
+            view: view.map(|view| &view.records),
+        }
+    }
+
+    fn insert_record(&mut self, name: Name, record: Record) {
+        let store = Arc::make_mut(self.records.get_mut().unwrap());
+        insert_into(&mut store.records, name.clone(), record.clone());
+        self.sources.push(Source::Record(name, record));
+    }
+
+    fn load_source(&mut self, source: Source) -> Result<()> {
+        source.load_into(Arc::make_mut(self.records.get_mut().unwrap()))?;
+        self.sources.push(source);
+
+        Ok(())
+    }
+
+    pub fn load_zone(&mut self, reader: impl BufRead) -> Result<()> {
+        let records = parse_zone(reader)?;
+        soa_owners(&records).for_each(|apex| insert_zone(&mut self.zones, apex));
+
+        for (name, record) in records {
+            self.insert_record(name, record);
+        }
+
+        Ok(())
+    }
+
+    pub fn load_zone_file(&mut self, path: &Path) -> Result<()> {
+        self.load_source(Source::Zone(path.to_path_buf()))
+    }
+
+    pub fn load_hosts_from(&mut self, reader: impl BufRead) -> Result<()> {
+        for (name, record) in parse_hosts(reader)? {
+            self.insert_record(name, record);
+        }
+
+        Ok(())
+    }
+
+    pub fn load_hosts(&mut self, path: &Path) -> Result<()> {
+        self.load_source(Source::Hosts(path.to_path_buf()))
+    }
+
+    pub fn reload(&self) -> Result<()> {
+        let mut store = Store::default();
+        for source in &self.sources {
+            source.load_into(&mut store)?;
+        }
+
+        *self.records.write().unwrap() = Arc::new(store);
+        Ok(())
+    }
+
+    fn owner(&self, records: &Records, name: &Name) -> Option<Name> {
+        let name = name.to_lowercase();
+        if records.contains_key(&name) {
+            return Some(name);
+        }
+
+        for encloser in iter::successors(name.parent(), Name::parent) {
+            let wildcard = Name::from([&["*".to_string()], &encloser.labels()[..]].concat());
+            if records.contains_key(&wildcard) {
+                return Some(wildcard);
+            }
+            if records.contains_key(&encloser) {
+                break;
+            }
+        }
+
+        None
+    }
+
+    /// Renders every loaded record in master file format, readable back by `load_zone`.
+    pub fn dump_zone(&self) -> String {
+        let store = self.records();
+        let mut names = store.records.keys().collect::<Vec<_>>();
+        names.sort_by_key(|name| (name.labels().len(), name.to_string()));
+
+        names.into_iter()
+             .flat_map(|name| store.records[name].iter().map(move |record| format!("{name}\t{record}\n")))
+             .collect()
+    }
+
+    pub fn lookup(&self, name: &Name, qtype: &QType) -> Vec<Record> {
+        self.lookup_in(&self.records_for(None), name, qtype)
+    }
+
+    fn lookup_in(&self, records: &Records, name: &Name, qtype: &QType) -> Vec<Record> {
+        let Some(owner) = self.owner(records, name) else {
+            return vec![];
+        };
+        let mut matching = records.get(&owner)
+                                  .into_iter()
+                                  .flatten()
+                                  .filter(|r| qtype.matches(r.rrtype()))
+                                  .cloned()
+                                  .collect::<Vec<_>>();
+
+        if matching.len() > 1 {
+            let first = self.rotation.fetch_add(1, Ordering::Relaxed) % matching.len();
+            matching.rotate_left(first);
+        }
+
+        matching
+    }
+}
+
+impl Debug for Server {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Server<{}, {}>", self.address, self.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use super::*;
+    use crate::{message::EDNS_COOKIE, testutil::allocations};
+
+    static CODECRAFTERS_QUERY: &[u8] = b"\xfd\xf0\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x0ccodecrafters\x02io\x00\x00\x01\x00\x01";
+    static CODECRAFTERS_RESPONSE: &[u8] = b"\xfd\xf0\x81\x00\x00\x01\x00\x01\x00\x00\x00\x00\x0ccodecrafters\x02io\x00\x00\x01\x00\x01\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x08\x08\x08\x08";
+    static EXAMPLE_ORG_QUERY: &[u8] = b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x07example\x03org\x00\x00\x01\x00\x01";
+
+    fn test_server() -> Result<Server> {
+        ServerBuilder::default().port(0).build()
+    }
+
+    fn respond(server: &Server, bytes: &[u8]) -> Result<Vec<u8>> {
+        server.process_message_bytes(bytes)
+    }
+
+    fn query_for(name: &str, rrtype: RRType) -> Result<Vec<u8>> {
+        Query::builder()
+            .id(0x4242)
+            .questions(vec![Question::new(name.parse()?, QType::RRType(rrtype), QClass::RRClass(RRClass::IN))])
+            .build()
+            .to_vec()
+    }
+
+    fn exchange_udp(server: &Server, bytes: &[u8]) -> Result<Vec<u8>> {
+        thread::scope(|s| {
+            let handle = s.spawn(|| server.serve());
+
+            let client = UdpSocket::bind("127.0.0.1:0")?;
+            client.set_read_timeout(Some(Duration::from_secs(5)))?;
+            client.send_to(bytes, server.local_addr()?)?;
+            let mut buf = [0; 4096];
+            let (size, _) = client.recv_from(&mut buf)?;
+
+            handle.join().expect("UDP server thread panicked")?;
+            Ok(buf[..size].to_vec())
+        })
+    }
+
+    fn fake_upstream<F>(reply: F) -> Result<(SocketAddr, Arc<AtomicUsize>)>
+    where
+        F: Fn(Query) -> Option<Response> + Send + 'static
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let address = socket.local_addr()?;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok((size, source)) = socket.recv_from(&mut buf) {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let Ok(query) = Query::try_from(&buf[..size]) else { continue };
+                if let Some(response) = reply(query) {
+                    if let Ok(bytes) = Vec::<u8>::try_from(response) {
+                        let _ = socket.send_to(&bytes, source);
+                    }
+                }
+            }
+        });
+
+        Ok((address, calls))
+    }
+
+    fn answering(address: &'static str, ttl: u32) -> impl Fn(Query) -> Option<Response> {
+        move |query| {
+            let answers = query.questions()
+                               .iter()
+                               .map(|q| Answer::new(q.name(), &Record::from_ip_v4(address).unwrap().with_ttl(ttl)))
+                               .collect();
+            Some(Response::builder()
+                .id(query.id())
+                .recursion_desired(query.recursion_desired())
+                .set_recursion_available()
+                .questions(query.questions())
+                .answers(answers)
+                .response_code(ResponseCode::NoError)
+                .build())
+        }
+    }
+
+    #[test]
+    fn build_on_ephemeral_port() -> Result<()> {
+        let server = test_server()?;
+
+        assert_ne!(0, server.local_addr()?.port());
+        assert_eq!("Server<127.0.0.1, 0>", format!("{server:?}"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn bind_failure_is_an_error() -> Result<()> {
+        let first = test_server()?;
+        let port = first.local_addr()?.port();
+
+        let second = ServerBuilder::default().port(port).build();
+        assert!(second.is_err());
+        assert_eq!(format!("failed to bind 127.0.0.1:{port}"), second.unwrap_err().to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_question_query() -> Result<()> {
+        let server = test_server()?;
+        let bytes = b"\xab\xcd\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+
+        let response = respond(&server, bytes)?;
+
+        assert_eq!(b"\xab\xcd\x81\x00\x00\x00\x00\x00\x00\x00\x00\x00", &response[..]);
+
+        let strict = ServerBuilder::default().port(0).set_strict().build()?;
+        let response = respond(&strict, bytes)?;
+        assert_eq!(b"\xab\xcd\x81\x01\x00\x00\x00\x00\x00\x00\x00\x00", &response[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn answers_from_record_store() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let response = respond(&server, CODECRAFTERS_QUERY)?;
+        assert_eq!([0, 1], response[6..8]);
+        assert!(response.ends_with(b"\x00\x04\x08\x08\x08\x08"));
+
+        let response = respond(&server, EXAMPLE_ORG_QUERY)?;
+        assert_eq!([0, 0], response[6..8]);
+        assert!(!response.ends_with(b"\x08\x08\x08\x08"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn configured_ttl_is_served() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?.with_ttl(300))?;
+
+        let response = Response::try_from(&respond(&server, CODECRAFTERS_QUERY)?[..])?;
+        assert_eq!(300, response.answers()[0].ttl());
+
+        Ok(())
+    }
+
+    #[test]
+    fn records_from_zone() -> Result<()> {
+        let mut server = test_server()?;
+        server.load_zone(Cursor::new("$ORIGIN codecrafters.io.\n@ 120 IN A 8.8.8.8\n"))?;
+
+        let response = Response::try_from(&respond(&server, CODECRAFTERS_QUERY)?[..])?;
+        assert_eq!(120, response.answers()[0].ttl());
+        assert_eq!(Some("8.8.8.8".into()), response.answers()[0].record().as_ipv4());
+        assert!(server.load_zone_file(Path::new("/nonexistent/zone")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn records_from_hosts() -> Result<()> {
+        let mut server = test_server()?;
+        server.load_hosts_from(Cursor::new("192.0.2.7 example.org codecrafters.io\n"))?;
+
+        for query in [EXAMPLE_ORG_QUERY, CODECRAFTERS_QUERY] {
+            let response = Response::try_from(&respond(&server, query)?[..])?;
+            assert_eq!(Some("192.0.2.7".into()), response.answers()[0].record().as_ipv4());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn reverse_lookup() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("4.3.2.1.in-addr.arpa", Record::from_ptr(&"host.example.com".parse()?))?;
+
+        let query = Query::builder()
+            .id(0x4321)
+            .questions(vec![Question::new(
+                "4.3.2.1.in-addr.arpa".parse()?,
+                QType::RRType(RRType::PTR),
+                QClass::RRClass(RRClass::IN),
+            )])
+            .build();
+        let response = Response::try_from(&respond(&server, &query.to_vec()?)?[..])?;
+
+        assert_eq!(1, response.answers().len());
+        assert_eq!("host.example.com".parse::<Name>()?, response.answers()[0].record().ptr_name()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_name_is_name_error_when_authoritative() -> Result<()> {
+        let mut server = ServerBuilder::default().port(0).mode(Mode::Authoritative).build()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let response = respond(&server, EXAMPLE_ORG_QUERY)?;
+        assert_eq!([0, 0], response[6..8]);
+        assert_eq!(3, response[3] & 0x0f);
+
+        let response = respond(&server, CODECRAFTERS_QUERY)?;
+        assert_eq!([0, 1], response[6..8]);
+        assert_eq!(0, response[3] & 0x0f);
+
+        Ok(())
+    }
+
+    #[test]
+    fn answers_within_authoritative_zones() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_zone("Example.org".parse()?);
+        server.add_record("www.example.org", Record::from_ip_v4("192.0.2.1")?)?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+        let ask = |name: &str| -> Result<Response> { Response::try_from(&respond(&server, &query_for(name, RRType::A)?)?[..]) };
+
+        let hit = ask("WWW.example.org")?;
+        assert_eq!(ResponseCode::NoError, hit.response_code());
+        assert!(hit.header().aa);
+        assert_eq!(1, hit.answers().len());
+
+        let miss = ask("mail.example.org")?;
+        assert_eq!(ResponseCode::NameError, miss.response_code());
+        assert!(miss.header().aa);
+
+        let outside = ask("example.net")?;
+        assert_eq!(ResponseCode::Refused, outside.response_code());
+        assert!(!outside.header().aa);
+
+        let local = ask("codecrafters.io")?;
+        assert_eq!(ResponseCode::NoError, local.response_code());
+        assert!(!local.header().aa);
+        assert_eq!(1, local.answers().len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn zone_files_declare_their_apex() -> Result<()> {
+        let mut server = test_server()?;
+        server.load_zone(Cursor::new("$ORIGIN example.net.\n@ IN SOA ns1 admin 1 7200 3600 1209600 300\n"))?;
+
+        let response = Response::try_from(&respond(&server, &query_for("www.example.net", RRType::A)?)?[..])?;
+        assert_eq!(ResponseCode::NameError, response.response_code());
+        assert!(response.header().aa);
+
+        let response = Response::try_from(&respond(&server, EXAMPLE_ORG_QUERY)?[..])?;
+        assert_eq!(ResponseCode::Refused, response.response_code());
+
+        Ok(())
+    }
+
+    #[test]
+    fn name_error_carries_zone_soa() -> Result<()> {
+        let mut server = test_server()?;
+        let soa = Record::from_soa(&"ns1.example.org".parse()?, &"admin.example.org".parse()?, 1, 7200, 3600, 1209600, 30)
+            .with_ttl(3600);
+        server.add_record("example.org", soa)?;
+        server.add_record("www.example.org", Record::from_ip_v4("192.0.2.1")?)?;
+
+        let bytes = respond(&server, EXAMPLE_ORG_QUERY)?;
+        assert_eq!(0, bytes[3] & 0x0f);
+        assert_eq!([0, 0], bytes[8..10]);
+
+        let mut query = EXAMPLE_ORG_QUERY.to_vec();
+        query.splice(12..12, *b"\x04mail");
+        let bytes = respond(&server, &query)?;
+        assert_eq!(3, bytes[3] & 0x0f);
+        assert_eq!([0, 1], bytes[8..10]);
+
+        let response = Response::try_from(&bytes[..])?;
+        assert_eq!(&"example.org".parse::<Name>()?, response.authority()[0].name());
+        assert_eq!(30, response.authority()[0].ttl());
+        assert_eq!(1, response.authority()[0].record().soa()?.serial);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cname_chain_is_followed() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("www.example.org", Record::from_cname(&"cname.example.org".parse()?))?;
+        server.add_record("cname.example.org", Record::from_cname(&"example.org".parse()?))?;
+        server.add_record("example.org", Record::from_ip_v4("192.0.2.1")?)?;
+        server.add_record("loop.example.org", Record::from_cname(&"loop.example.org".parse()?))?;
+
+        let mut query = EXAMPLE_ORG_QUERY.to_vec();
+        query.splice(12..12, *b"\x03www");
+        let response = Response::try_from(&respond(&server, &query)?[..])?;
+        let chain = response.answers()
+                            .iter()
+                            .map(|a| a.name().to_string())
+                            .collect::<Vec<_>>();
+
+        assert_eq!(vec!["www.example.org.", "cname.example.org.", "example.org."], chain);
+        assert_eq!(Some("192.0.2.1".to_string()), response.answers()[2].record().as_ipv4());
+
+        let mut query = EXAMPLE_ORG_QUERY.to_vec();
+        query.splice(12..12, *b"\x04loop");
+        let response = Response::try_from(&respond(&server, &query)?[..])?;
+        assert_eq!(MAX_CNAME_HOPS + 1, response.answers().len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn any_query_returns_every_type() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("example.org", Record::from_ip_v4("192.0.2.1")?)?;
+        server.add_record("example.org", Record::from_mx(10, &"mail.example.org".parse()?))?;
+        server.add_record("example.org", Record::from_txt(&["v=spf1 -all"])?)?;
+
+        let mut query = EXAMPLE_ORG_QUERY.to_vec();
+        query[26] = 255;
+        let response = Response::try_from(&respond(&server, &query)?[..])?;
+        let mut types = response.answers()
+                                .iter()
+                                .map(|a| u16::from(a.record().rrtype().clone()))
+                                .collect::<Vec<_>>();
+        types.sort();
+
+        assert_eq!(ResponseCode::NoError, response.response_code());
+        assert_eq!(vec![1, 15, 16], types);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_opcode_is_not_implemented() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let mut query = CODECRAFTERS_QUERY.to_vec();
+        query[2] = 0x09;
+        let response = respond(&server, &query)?;
+
+        assert_eq!(4, response[3] & 0x0f);
+        assert_eq!([0, 1, 0, 0], response[4..8]);
+        assert_eq!(query[12..], response[12..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mismatched_type_is_empty_no_error() -> Result<()> {
+        let mut server = ServerBuilder::default().port(0).mode(Mode::Authoritative).build()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let mut query = CODECRAFTERS_QUERY.to_vec();
+        let qtype = query.len() - 3;
+        query[qtype] = 28;
+
+        let response = respond(&server, &query)?;
+        assert_eq!([0, 0], response[6..8]);
+        assert_eq!(0, response[3] & 0x0f);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_qtype_and_qclass_are_echoed() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let mut query = CODECRAFTERS_QUERY.to_vec();
+        let end = query.len();
+        query[end - 4..].copy_from_slice(b"\x00\x40\x00\x64");
+
+        let bytes = respond(&server, &query)?;
+        let response = Response::try_from(&bytes[..])?;
+        assert_eq!(ResponseCode::NoError, response.response_code());
+        assert!(response.answers().is_empty());
+        assert_eq!(&query[12..], &bytes[12..]);
+        assert_eq!(&QType::RRType(RRType::Unknown(64)), response.questions()[0].qtype());
+        assert_eq!(&QClass::RRClass(RRClass::Unknown(100)), response.questions()[0].qclass());
+
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_records_rotate() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("1.1.1.1")?)?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("2.2.2.2")?)?;
+
+        let first = respond(&server, CODECRAFTERS_QUERY)?;
+        let second = respond(&server, CODECRAFTERS_QUERY)?;
+
+        assert_eq!([0, 2], first[6..8]);
+        assert!(first.ends_with(b"\x01\x01\x01\x01\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x02\x02\x02\x02"));
+        assert_eq!([0, 2], second[6..8]);
+        assert!(second.ends_with(b"\x02\x02\x02\x02\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x01\x01\x01\x01"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn case_insensitive_lookup() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let query = b"\xfd\xf0\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x0cCodeCrafters\x02IO\x00\x00\x01\x00\x01";
+        let response = respond(&server, query)?;
+
+        assert_eq!([0, 1], response[6..8]);
+        assert_eq!(b"\x0cCodeCrafters\x02IO\x00", &response[12..29]);
+        assert!(response.ends_with(b"\x08\x08\x08\x08"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn serve_over_tcp() -> Result<()> {
+        let mut server = ServerBuilder::default().port(0).set_tcp().build()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let response = thread::scope(|s| {
+            let handle = s.spawn(|| server.serve_tcp());
+
+            let mut stream = TcpStream::connect(server.local_addr()?)?;
+            write_framed(&mut stream, CODECRAFTERS_QUERY)?;
+            let response = read_framed(&mut stream)?;
+            drop(stream);
+
+            handle.join().expect("TCP server thread panicked")?;
+            anyhow::Ok(response)
+        })?;
+
+        let response = Response::try_from(&response.expect("Missing TCP response")[..])?;
+        assert_eq!(0xfdf0, response.id());
+        assert_eq!(1, response.answers().len());
+        assert_eq!(Some("8.8.8.8".into()), response.answers()[0].record().as_ipv4());
+
+        Ok(())
+    }
+
+    #[test]
+    fn large_udp_answers_are_truncated() -> Result<()> {
+        let mut server = test_server()?;
+        for k in 0..40 {
+            server.add_record("codecrafters.io", Record::from_ip_v4(&format!("10.0.0.{k}"))?)?;
+        }
+
+        let response = exchange_udp(&server, CODECRAFTERS_QUERY)?;
+
+        assert!(response.len() <= 512);
+        let response = Response::try_from(&response[..])?;
+        assert!(response.truncation());
+        assert!(response.answers().is_empty());
+        assert_eq!(1, response.questions().len());
+
+        let full = Response::try_from(&respond(&server, CODECRAFTERS_QUERY)?[..])?;
+        assert!(!full.truncation());
+        assert_eq!(40, full.answers().len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn access_control_lists() -> Result<()> {
+        for (network, rcode, answers) in [("127.0.0.0/8", 0, 1), ("10.0.0.0/8", 5, 0)] {
+            let mut server = ServerBuilder::default().port(0).allow("192.168.0.0/16").allow(network).build()?;
+            server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+            let response = Response::try_from(&exchange_udp(&server, CODECRAFTERS_QUERY)?[..])?;
+            assert_eq!(rcode, u8::from(response.response_code()));
+            assert_eq!(answers, response.answers().len());
+        }
+
+        assert!(ServerBuilder::default().port(0).allow("10.0.0.0/99").build().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rate_limit_is_per_source() -> Result<()> {
+        let server = ServerBuilder::default().port(0).rate_limit(1, 5).build()?;
+        let noisy = IpAddr::from([10, 0, 0, 1]);
+
+        assert_eq!(5, (0..50).filter(|_| server.within_rate(noisy)).count());
+        assert!(server.within_rate(IpAddr::from([10, 0, 0, 2])));
+        assert!(test_server()?.within_rate(noisy));
+
+        Ok(())
+    }
+
+    #[test]
+    fn zone_transfer_over_tcp() -> Result<()> {
+        let mut server = ServerBuilder::default().port(0).set_tcp().build()?;
+        server.load_zone(Cursor::new("\
+$ORIGIN example.org.
+@    IN SOA ns1 admin 7 7200 3600 1209600 300
+@    IN NS  ns1
+ns1  IN A   192.0.2.1
+www  IN A   192.0.2.2
+other.test. IN A 192.0.2.3
+"))?;
+
+        let mut query = EXAMPLE_ORG_QUERY.to_vec();
+        query[26] = 252;
+
+        let messages = thread::scope(|s| {
+            let handle = s.spawn(|| server.serve_tcp());
+
+            let mut stream = TcpStream::connect(server.local_addr()?)?;
+            write_framed(&mut stream, &query)?;
+            let mut messages = vec![];
+            let mut soas = 0;
+            while soas < 2 {
+                let message = read_framed(&mut stream)?.expect("Transfer ended early");
+                let response = Response::try_from(&message[..])?;
+                soas += response.answers().iter().filter(|a| *a.record().rrtype() == RRType::SOA).count();
+                messages.push(response);
+            }
+            drop(stream);
+
+            handle.join().expect("TCP server thread panicked")?;
+            anyhow::Ok(messages)
+        })?;
+
+        let types = messages.iter()
+                            .flat_map(|m| m.answers().iter().map(|a| a.record().rrtype().clone()))
+                            .collect::<Vec<_>>();
+        assert_eq!(vec![RRType::SOA, RRType::NS, RRType::A, RRType::A, RRType::SOA], types);
+        assert_eq!(1, messages[0].questions().len());
+        assert!(messages.iter().all(|m| m.id() == 0x1234));
+
+        let mut query = CODECRAFTERS_QUERY.to_vec();
+        query[30] = 252;
+        let mut input = vec![];
+        write_framed(&mut input, &query)?;
+        let mut output = vec![];
+        server.handle_tcp_stream(&mut Cursor::new(input), &mut output, None)?;
+        let refused = read_framed(&mut Cursor::new(output))?.expect("Missing response");
+        assert_eq!(5, refused[3] & 0x0f);
+
+        Ok(())
+    }
+
+    #[test]
+    fn edns_payload_size_is_honored() -> Result<()> {
+        let mut server = test_server()?;
+        for k in 0..40 {
+            server.add_record("codecrafters.io", Record::from_ip_v4(&format!("10.0.0.{k}"))?)?;
+        }
+
+        let query = Query::builder()
+            .id(0xfdf0)
+            .questions(Query::try_from(CODECRAFTERS_QUERY)?.questions())
+            .edns(Edns::new(4096))
+            .build();
+        let bytes = exchange_udp(&server, &query.to_vec()?)?;
+        let response = Response::try_from(&bytes[..])?;
+
+        assert!(bytes.len() > 512);
+        assert!(!response.truncation());
+        assert_eq!(40, response.answers().len());
+        assert_eq!(Some(4096), response.edns().map(|edns| edns.payload_size()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn exchange_reports_edns_parameters() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let query = Query::builder()
+            .id(0xfdf0)
+            .questions(Query::try_from(CODECRAFTERS_QUERY)?.questions())
+            .edns(Edns::new(4096).set_dnssec_ok().option(EDNS_COOKIE, b"clientck"))
+            .build();
+        let exchange = server.exchange(&query.to_vec()?, None)?;
+        let edns = exchange.edns.expect("OPT record is reported");
+
+        assert_eq!(4096, exchange.payload_size);
+        assert_eq!((4096, true, 0), (edns.payload_size(), edns.dnssec_ok(), edns.version()));
+        assert!(edns.has_cookie());
+        assert!(!edns.has_nsid());
+        assert_eq!(1, exchange.response.answers().len());
+
+        let plain = server.exchange(CODECRAFTERS_QUERY, None)?;
+        assert_eq!((None, 512), (plain.edns, plain.payload_size));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_edns_version_is_badvers() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let query = Query::builder()
+            .id(0xfdf0)
+            .questions(Query::try_from(CODECRAFTERS_QUERY)?.questions())
+            .edns(Edns::new(4096).with_version(1))
+            .build();
+        let bytes = respond(&server, &query.to_vec()?)?;
+        let response = Response::try_from(&bytes[..])?;
+
+        assert_eq!(0, bytes[3] & 0x0f);
+        assert_eq!(ResponseCode::BadVersion, response.response_code());
+        assert!(response.answers().is_empty());
+        assert_eq!(Some(0), response.edns().map(|edns| edns.version()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn forwarded_answers_are_cached() -> Result<()> {
+        let (upstream, calls) = fake_upstream(answering("1.2.3.4", 300))?;
+        let server = ServerBuilder::default().port(0).upstream(upstream).build()?;
+
+        let first = Response::try_from(&respond(&server, EXAMPLE_ORG_QUERY)?[..])?;
+        let second = Response::try_from(&respond(&server, EXAMPLE_ORG_QUERY)?[..])?;
+
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+        for response in [&first, &second] {
+            assert_eq!(0x1234, response.id());
+            assert_eq!(1, response.answers().len());
+            assert_eq!(Some("1.2.3.4".into()), response.answers()[0].record().as_ipv4());
+        }
+        assert!(second.answers()[0].ttl() <= 300);
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_local_answers_are_authoritative() -> Result<()> {
+        let (upstream, _) = fake_upstream(answering("1.2.3.4", 300))?;
+        let mut server = ServerBuilder::default().port(0).upstream(upstream).build()?;
+        server.add_zone("codecrafters.io".parse()?);
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let local = respond(&server, CODECRAFTERS_QUERY)?;
+        assert_eq!(0x04, local[2] & 0x04);
+        assert_eq!([0, 1], local[6..8]);
+
+        for _ in 0..2 {
+            let forwarded = respond(&server, EXAMPLE_ORG_QUERY)?;
+            assert_eq!(0, forwarded[2] & 0x04);
+            assert_eq!([0, 1], forwarded[6..8]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn recursion_available_only_with_upstream() -> Result<()> {
+        let mut local = test_server()?;
+        local.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let response = respond(&local, CODECRAFTERS_QUERY)?;
+        assert_eq!(0x01, response[2] & 0x01);
+        assert_eq!(0, response[3] & 0x80);
+        assert_eq!([0, 1], response[6..8]);
+
+        let (upstream, _) = fake_upstream(answering("1.2.3.4", 300))?;
+        let mut forwarding = ServerBuilder::default().port(0).upstream(upstream).build()?;
+        forwarding.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        for query in [CODECRAFTERS_QUERY, EXAMPLE_ORG_QUERY] {
+            let response = respond(&forwarding, query)?;
+            assert_eq!(0x80, response[3] & 0x80);
+            assert_eq!([0, 1], response[6..8]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn negative_answers_are_cached() -> Result<()> {
+        let (upstream, calls) = fake_upstream(|query| {
+            let soa = Record::from_soa(&"ns1.example.org".parse().unwrap(), &"admin.example.org".parse().unwrap(),
+                                       1, 7200, 3600, 1209600, 30).with_ttl(3600);
+            Some(Response::builder()
+                .id(query.id())
+                .questions(query.questions())
+                .authority(vec![Answer::new(&"example.org".parse().unwrap(), &soa)])
+                .response_code(ResponseCode::NameError)
+                .build())
+        })?;
+        let server = ServerBuilder::default().port(0).upstream(upstream).build()?;
+
+        let mut query = EXAMPLE_ORG_QUERY.to_vec();
+        query.splice(12..12, *b"\x04mail");
+        let first = Response::try_from(&respond(&server, &query)?[..])?;
+        let second = Response::try_from(&respond(&server, &query)?[..])?;
+
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+        for response in [&first, &second] {
+            assert_eq!(ResponseCode::NameError, response.response_code());
+            assert_eq!(0, response.answers().len());
+            assert_eq!(1, response.authority().len());
+        }
+        assert!(second.authority()[0].ttl() <= 30);
+        assert_eq!(1, server.stats().cache_hits);
+
+        Ok(())
+    }
+
+    #[test]
+    fn near_expiry_entries_are_prefetched() -> Result<()> {
+        let (upstream, calls) = fake_upstream(answering("1.2.3.4", 2))?;
+        let server = ServerBuilder::default().port(0).upstream(upstream).prefetch(true).build()?;
+        let start = Instant::now();
+
+        respond(&server, EXAMPLE_ORG_QUERY)?;
+        thread::sleep(Duration::from_millis(1850).saturating_sub(start.elapsed()));
+        let near_expiry = Response::try_from(&respond(&server, EXAMPLE_ORG_QUERY)?[..])?;
+        assert_eq!(1, near_expiry.answers().len());
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while calls.load(Ordering::SeqCst) < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+
+        thread::sleep(Duration::from_millis(2200).saturating_sub(start.elapsed()));
+        let refreshed = Response::try_from(&respond(&server, EXAMPLE_ORG_QUERY)?[..])?;
+        assert_eq!(1, refreshed.answers().len());
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+        assert_eq!(1, server.stats().cache_misses);
+        assert_eq!(2, server.stats().cache_hits);
+
+        Ok(())
+    }
+
+    #[test]
+    fn failover_to_next_upstream() -> Result<()> {
+        let (silent, silent_calls) = fake_upstream(|_| None)?;
+        let (failing, failing_calls) = fake_upstream(|query| {
+            Some(Response::builder()
+                .id(query.id())
+                .questions(query.questions())
+                .response_code(ResponseCode::ServerFailure)
+                .build())
+        })?;
+        let (working, working_calls) = fake_upstream(answering("1.2.3.4", 300))?;
+        let server = ServerBuilder::default()
+            .port(0)
+            .upstream(silent)
+            .upstream(failing)
+            .upstream(working)
+            .upstream_timeout(Duration::from_millis(200))
+            .build()?;
+
+        let response = Response::try_from(&respond(&server, EXAMPLE_ORG_QUERY)?[..])?;
+        assert_eq!(ResponseCode::NoError, response.response_code());
+        assert_eq!(Some("1.2.3.4".into()), response.answers()[0].record().as_ipv4());
+        assert!(silent_calls.load(Ordering::SeqCst) >= 1);
+        assert_eq!(1, failing_calls.load(Ordering::SeqCst));
+        assert_eq!(1, working_calls.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_upstreams_use_fastest_answer() -> Result<()> {
+        let slow = answering("6.6.6.6", 300);
+        let (slow, _) = fake_upstream(move |query| {
+            thread::sleep(Duration::from_millis(300));
+            slow(query)
+        })?;
+        let (fast, fast_calls) = fake_upstream(answering("1.2.3.4", 300))?;
+        let server = ServerBuilder::default()
+            .port(0)
+            .upstream(slow)
+            .upstream(fast)
+            .upstream_strategy(UpstreamStrategy::Parallel)
+            .build()?;
+
+        let response = Response::try_from(&respond(&server, EXAMPLE_ORG_QUERY)?[..])?;
+        assert_eq!(Some("1.2.3.4".into()), response.answers()[0].record().as_ipv4());
+        assert_eq!(1, fast_calls.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[test]
+    fn silent_upstream_is_server_failure() -> Result<()> {
+        let (upstream, calls) = fake_upstream(|_| None)?;
+        let server = ServerBuilder::default()
+            .port(0)
+            .upstream(upstream)
+            .upstream_timeout(Duration::from_millis(200))
+            .upstream_retries(2)
+            .build()?;
+
+        let start = Instant::now();
+        let response = respond(&server, EXAMPLE_ORG_QUERY)?;
+        assert_eq!(2, response[3] & 0x0f);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(3, calls.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[test]
+    fn prewarm_fills_the_cache() -> Result<()> {
+        let (upstream, calls) = fake_upstream(answering("1.2.3.4", 300))?;
+        let server = ServerBuilder::default().port(0).upstream(upstream).build()?;
+        let a = QType::RRType(RRType::A);
+
+        server.prewarm(vec![("Example.org", a.clone()), ("www.example.org", a.clone()), ("bad..name", a.clone())]);
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+        for name in ["example.org", "www.example.org"] {
+            let key = (name.parse()?, a.clone(), QClass::RRClass(RRClass::IN));
+            assert!(server.cache.lock().unwrap().get(&key).is_some());
+        }
+
+        let response = Response::try_from(&respond(&server, EXAMPLE_ORG_QUERY)?[..])?;
+        assert_eq!(Some("1.2.3.4".into()), response.answers()[0].record().as_ipv4());
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[test]
+    fn forward_timeouts_are_counted() -> Result<()> {
+        let (upstream, calls) = fake_upstream(|_| None)?;
+        let server = ServerBuilder::default().port(0).upstream(upstream).upstream_retries(0).build()?;
+        server.set_forward_timeout(Duration::from_millis(100));
+
+        let start = Instant::now();
+        let response = respond(&server, EXAMPLE_ORG_QUERY)?;
+        assert_eq!(2, response[3] & 0x0f);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        let stats = server.stats();
+        assert_eq!(1, stats.upstream_timeouts);
+        assert_eq!(1, stats.responses(&ResponseCode::ServerFailure));
+
+        Ok(())
+    }
+
+    #[test]
+    fn mode_gates_local_and_forwarded_answers() -> Result<()> {
+        let (upstream, calls) = fake_upstream(answering("1.2.3.4", 300))?;
+        let cases = [
+            (Mode::Authoritative, [(ResponseCode::NoError, Some("8.8.8.8")), (ResponseCode::NameError, None)]),
+            (Mode::Forwarding, [(ResponseCode::NoError, Some("1.2.3.4")), (ResponseCode::NoError, Some("1.2.3.4"))]),
+            (Mode::Hybrid, [(ResponseCode::NoError, Some("8.8.8.8")), (ResponseCode::NoError, Some("1.2.3.4"))]),
+        ];
+
+        for (mode, expected) in cases {
+            let mut server = ServerBuilder::default().port(0).upstream(upstream).mode(mode).build()?;
+            server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+            for (query, (response_code, address)) in iter::zip([CODECRAFTERS_QUERY, EXAMPLE_ORG_QUERY], expected) {
+                let response = Response::try_from(&respond(&server, query)?[..])?;
+                assert_eq!(response_code, response.response_code(), "{mode:?}");
+                assert_eq!(address.map(String::from), response.answers().first().and_then(|a| a.record().as_ipv4()), "{mode:?}");
+            }
+        }
+        assert_eq!(3, calls.load(Ordering::SeqCst));
+
+        let server = ServerBuilder::default().port(0).mode(Mode::Forwarding).build()?;
+        assert_eq!(ResponseCode::Refused, Response::try_from(&respond(&server, EXAMPLE_ORG_QUERY)?[..])?.response_code());
+
+        Ok(())
+    }
+
+    #[test]
+    fn checking_disabled_is_echoed() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let mut query = CODECRAFTERS_QUERY.to_vec();
+        query[3] = 0x30;
+
+        let response = respond(&server, &query)?;
+        assert_eq!(0x10, response[3]);
+        assert_eq!([0, 1], response[6..8]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn forwarded_queries_use_fresh_ids() -> Result<()> {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let recorder = seen.clone();
+        let reply = answering("1.2.3.4", 300);
+        let (upstream, _) = fake_upstream(move |query| {
+            recorder.lock().unwrap().push(query.id());
+            reply(query)
+        })?;
+        let server = ServerBuilder::default()
+            .port(0)
+            .upstream(upstream)
+            .id_source(StdRng::seed_from_u64(42))
+            .build()?;
+
+        let (first, second) = thread::scope(|s| {
+            let first = s.spawn(|| respond(&server, CODECRAFTERS_QUERY));
+            let second = s.spawn(|| respond(&server, EXAMPLE_ORG_QUERY));
+            (first.join().expect("client thread panicked"), second.join().expect("client thread panicked"))
+        });
+
+        assert_eq!(0xfdf0, Response::try_from(&first?[..])?.id());
+        assert_eq!(0x1234, Response::try_from(&second?[..])?.id());
+
+        let mut expected = StdRng::seed_from_u64(42);
+        let mut expected = vec![expected.gen::<u16>(), expected.gen::<u16>()];
+        let mut seen = seen.lock().unwrap().clone();
+        expected.sort();
+        seen.sort();
+        assert_ne!(seen[0], seen[1]);
+        assert_eq!(expected, seen);
+
+        Ok(())
+    }
+
+    #[test]
+    fn upstream_failure_is_relayed() -> Result<()> {
+        let (upstream, _) = fake_upstream(|query| {
+            Some(Response::builder()
+                .id(query.id())
+                .questions(query.questions())
+                .response_code(ResponseCode::ServerFailure)
+                .build())
+        })?;
+        let server = ServerBuilder::default().port(0).upstream(upstream).build()?;
+
+        let response = respond(&server, EXAMPLE_ORG_QUERY)?;
+        assert_eq!(2, response[3] & 0x0f);
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_response_policies() -> Result<()> {
+        let policies = [
+            (EmptyPolicy::NoError, 0),
+            (EmptyPolicy::NameError, 3),
+            (EmptyPolicy::Refused, 5),
+            (EmptyPolicy::ServerFailure, 2),
+        ];
+
+        for (policy, rcode) in policies {
+            let mut server = ServerBuilder::default().port(0).empty_response_policy(policy).build()?;
+            server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+            let response = respond(&server, EXAMPLE_ORG_QUERY)?;
+
+            assert_eq!(rcode, response[3] & 0x0f);
+            assert_eq!([0, 0], response[6..8]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn idle_tcp_client_does_not_block_others() -> Result<()> {
+        let mut server = ServerBuilder::default().port(0).set_tcp().build()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+        let shutdown = AtomicBool::new(false);
+
+        let response = thread::scope(|s| {
+            let handle = s.spawn(|| server.serve_tcp_forever(4, &shutdown));
+
+            let idle = TcpStream::connect(server.local_addr()?)?;
+            let mut stream = TcpStream::connect(server.local_addr()?)?;
+            stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+            write_framed(&mut stream, CODECRAFTERS_QUERY)?;
+            let response = read_framed(&mut stream);
+
+            drop((idle, stream));
+            shutdown.store(true, Ordering::SeqCst);
+            handle.join().expect("TCP server thread panicked")?;
+            response
+        })?;
+
+        let response = Response::try_from(&response.expect("Missing response")[..])?;
+        assert_eq!(Some("8.8.8.8".into()), response.answers()[0].record().as_ipv4());
+
+        Ok(())
+    }
+
+    #[test]
+    fn tcp_stream_answers_every_message() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let mut input = vec![];
+        write_framed(&mut input, CODECRAFTERS_QUERY)?;
+        write_framed(&mut input, EXAMPLE_ORG_QUERY)?;
+
+        let mut output = vec![];
+        server.handle_tcp_stream(&mut Cursor::new(input), &mut output, None)?;
+
+        let mut reader = Cursor::new(output);
+        let first = read_framed(&mut reader)?.expect("Missing first response");
+        let second = read_framed(&mut reader)?.expect("Missing second response");
+        assert_eq!(None, read_framed(&mut reader)?);
+
+        assert_eq!(server.process_message_bytes(CODECRAFTERS_QUERY)?, first);
+        assert_eq!(0, first[2] & 0x02);
+        assert_eq!([0, 1], first[6..8]);
+        assert_eq!([0, 0], second[6..8]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn respond_without_socket() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        assert_eq!(CODECRAFTERS_RESPONSE, server.respond_to(CODECRAFTERS_QUERY));
+        assert!(server.respond_to(b"\xfd\xf0\x81").is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_queries_are_answered() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+        let address = server.local_addr()?;
+        let server = Arc::new(server);
+
+        let worker = server.clone();
+        thread::spawn(move || worker.serve_forever(4, &AtomicBool::new(false)));
+
+        let clients = (0..8).map(|_| thread::spawn(move || {
+            Client::default().query(address, &"codecrafters.io".parse()?, QType::RRType(RRType::A))
+        })).collect::<Vec<_>>();
+
+        for client in clients {
+            let response = client.join().expect("client thread panicked")?;
+            assert_eq!(ResponseCode::NoError, response.response_code());
+            assert_eq!(Some("8.8.8.8".into()), response.answers()[0].record().as_ipv4());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn serve_forever_stops_on_shutdown() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+        let address = server.local_addr()?;
+        let shutdown = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            let handle = s.spawn(|| server.serve_forever(2, &shutdown));
+
+            let response = Client::default().query(address, &"codecrafters.io".parse()?, QType::RRType(RRType::A))?;
+            assert_eq!(1, response.answers().len());
+
+            shutdown.store(true, Ordering::SeqCst);
+            handle.join().expect("UDP server thread panicked")
+        })?;
+
+        drop(server);
+        assert!(UdpSocket::bind(address).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reload_rereads_zone_file() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("reload-{}.zone", std::process::id()));
+        fs::write(&path, "$ORIGIN example.com.\nwww 300 IN A 192.0.2.1\n")?;
+
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+        server.load_zone_file(&path)?;
+        let www: Name = "www.example.com".parse()?;
+        let a = QType::RRType(RRType::A);
+        assert_eq!(Some("192.0.2.1".into()), server.lookup(&www, &a)[0].as_ipv4());
+
+        fs::write(&path, "$ORIGIN example.com.\nwww 300 IN A 192.0.2.9\nmail 300 IN A 192.0.2.25\n")?;
+        server.reload()?;
+        fs::remove_file(&path)?;
+
+        assert_eq!(vec![Record::from_ip_v4("192.0.2.9")?.with_ttl(300)], server.lookup(&www, &a));
+        assert_eq!(1, server.lookup(&"mail.example.com".parse()?, &a).len());
+        assert_eq!(1, server.lookup(&"codecrafters.io".parse()?, &a).len());
+        assert!(server.reload().is_err());
+        assert_eq!(1, server.lookup(&www, &a).len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reload_follows_zone_apex() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("reload-apex-{}.zone", std::process::id()));
+        fs::write(&path, "$ORIGIN example.net.\n@ IN SOA ns1 admin 1 7200 3600 1209600 300\nwww 300 IN A 192.0.2.1\n")?;
+
+        let mut server = test_server()?;
+        server.load_zone_file(&path)?;
+        let answer = |name: &str| -> Result<Response> {
+            Response::try_from(&respond(&server, &query_for(name, RRType::A)?)?[..])
+        };
+
+        let response = answer("www.example.net")?;
+        assert!(response.header().aa);
+        assert_eq!(ResponseCode::Refused, answer("www.example.org")?.response_code());
+
+        fs::write(&path, "$ORIGIN example.org.\n@ IN SOA ns1 admin 2 7200 3600 1209600 300\nwww 300 IN A 192.0.2.2\n")?;
+        server.reload()?;
+        fs::remove_file(&path)?;
+
+        let response = answer("www.example.org")?;
+        assert_eq!(ResponseCode::NoError, response.response_code());
+        assert!(response.header().aa);
+        assert_eq!(ResponseCode::NameError, answer("mail.example.org")?.response_code());
+        assert_eq!(ResponseCode::Refused, answer("www.example.net")?.response_code());
+
+        Ok(())
+    }
+
+    #[test]
+    fn dumped_zone_loads_back() -> Result<()> {
+        let zone = "\
+$ORIGIN example.com.
+$TTL 3600
+@ IN SOA ns1 admin 1 7200 3600 1209600 300
+@ IN NS ns1
+@ IN MX 10 mail
+ns1 IN A 192.0.2.53
+www 300 IN A 192.0.2.80
+www IN AAAA 2001:db8::80
+ftp IN CNAME www
+_sip._udp IN SRV 10 5 5060 sip
+@ IN TXT \"v=spf1 -all\" \"second string\"
+quotes IN TXT \"say \\\"hi\\\"\" \"back\\\\slash\"
+opaque IN TYPE65280 \\# 3 abcdef
+version CH TXT \"chaos\"
+";
+        let mut server = test_server()?;
+        server.load_zone(Cursor::new(zone))?;
+        let dump = server.dump_zone();
+
+        let mut reloaded = test_server()?;
+        reloaded.load_zone(Cursor::new(&dump))?;
+        assert_eq!(dump, reloaded.dump_zone());
+
+        let sorted = |records: Vec<(Name, Record)>| {
+            let mut lines = records.iter().map(|(name, record)| format!("{name} {record}")).collect::<Vec<_>>();
+            lines.sort();
+            lines
+        };
+        assert_eq!(sorted(parse_zone(Cursor::new(zone))?), sorted(parse_zone(Cursor::new(&dump))?));
+        assert!(dump.starts_with("example.com.\t3600\tIN\tSOA\t"));
+        assert!(dump.contains("quotes.example.com.\t3600\tIN\tTXT\t\"say \\\"hi\\\"\" \"back\\\\slash\"\n"));
+        assert!(dump.contains("opaque.example.com.\t3600\tIN\tTYPE65280\t\\# 3 abcdef\n"));
+        assert!(dump.contains("version.example.com.\t3600\tCH\tTXT\t\"chaos\"\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn stats_count_outcomes() -> Result<()> {
+        let (upstream, _) = fake_upstream(answering("1.2.3.4", 300))?;
+        let mut server = ServerBuilder::default().port(0).upstream(upstream).build()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        let mut not_implemented = CODECRAFTERS_QUERY.to_vec();
+        not_implemented[2] = 0x11;
+
+        respond(&server, CODECRAFTERS_QUERY)?;
+        respond(&server, EXAMPLE_ORG_QUERY)?;
+        respond(&server, EXAMPLE_ORG_QUERY)?;
+        respond(&server, &not_implemented)?;
+
+        let stats = server.stats();
+        assert_eq!(4, stats.queries);
+        assert_eq!(3, stats.responses(&ResponseCode::NoError));
+        assert_eq!(1, stats.responses(&ResponseCode::NotImplemented));
+        assert_eq!(3, stats.answers(&RRType::A));
+        assert_eq!(1, stats.forwards);
+        assert_eq!((1, 1), (stats.cache_hits, stats.cache_misses));
+
+        Ok(())
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn queries_are_logged_as_json() -> Result<()> {
+        let log = SharedBuffer::default();
+        let mut server = ServerBuilder::default().port(0).log_json(true).log_sink(log.clone()).build()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+
+        exchange_udp(&server, CODECRAFTERS_QUERY)?;
+
+        let log = String::from_utf8(log.0.lock().unwrap().clone())?;
+        let line = log.strip_suffix('\n').expect("log line should end with a newline");
+        assert!(!line.contains('\n'));
+        assert!(line.starts_with("{\"timestamp\":"));
+        assert!(line.contains(",\"source\":\"127.0.0.1\",\"id\":65008,\"qname\":\"codecrafters.io.\",\"qtype\":1,\"rcode\":0,\"answers\":1,\"latency_us\":"));
+        assert!(line.ends_with('}'));
+
+        Ok(())
+    }
+
+    #[test]
+    fn refused_queries_are_logged() -> Result<()> {
+        let log = SharedBuffer::default();
+        let server = ServerBuilder::default().port(0).allow("10.0.0.0/8").log_json(true).log_sink(log.clone()).build()?;
+
+        exchange_udp(&server, CODECRAFTERS_QUERY)?;
+
+        let log = String::from_utf8(log.0.lock().unwrap().clone())?;
+        assert!(log.contains("\"qname\":\"codecrafters.io.\",\"qtype\":1,\"rcode\":5,\"answers\":0,"), "{log}");
+
+        Ok(())
+    }
+
+    fn sinkhole_server(mode: Sinkhole) -> Result<Server> {
+        let mut server = ServerBuilder::default().port(0).sinkhole_mode(mode).build()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+        server.add_record("ads.example.org", Record::from_ip_v4("192.0.2.1")?)?;
+        server.add_blocklist(["Example.org.", "tracker.net"].into_iter())?;
+
+        Ok(server)
+    }
+
+    fn sinkhole_responses(mode: Sinkhole) -> Result<(Response, Response)> {
+        let server = sinkhole_server(mode)?;
+        let mut subdomain = Query::try_from(EXAMPLE_ORG_QUERY)?.questions();
+        subdomain[0] = Question::new("ads.example.org".parse()?, subdomain[0].qtype().clone(), subdomain[0].qclass().clone());
+        let blocked = Query::builder().id(0x1234).questions(subdomain).build();
+
+        Ok((
+            Response::try_from(&respond(&server, &blocked.to_vec()?)?[..])?,
+            Response::try_from(&respond(&server, CODECRAFTERS_QUERY)?[..])?,
+        ))
+    }
+
+    #[test]
+    fn sinkhole_nxdomain() -> Result<()> {
+        let (blocked, allowed) = sinkhole_responses(Sinkhole::NxDomain)?;
+
+        assert_eq!(ResponseCode::NameError, blocked.response_code());
+        assert!(blocked.answers().is_empty());
+        assert_eq!(Some("8.8.8.8".into()), allowed.answers()[0].record().as_ipv4());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sinkhole_null_ip() -> Result<()> {
+        let (blocked, allowed) = sinkhole_responses(Sinkhole::NullIp)?;
+
+        assert_eq!(ResponseCode::NoError, blocked.response_code());
+        assert_eq!(vec![Answer::new(&"ads.example.org".parse()?, &Record::from_ip_v4("0.0.0.0")?)], blocked.answers());
+        assert_eq!(Some("8.8.8.8".into()), allowed.answers()[0].record().as_ipv4());
+
+        let server = sinkhole_server(Sinkhole::NullIp)?;
+        let mut aaaa = EXAMPLE_ORG_QUERY.to_vec();
+        aaaa[26] = 28;
+        let response = Response::try_from(&respond(&server, &aaaa)?[..])?;
+        assert_eq!(vec![Answer::new(&"example.org".parse()?, &Record::from_ip_v6("::")?)], response.answers());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sinkhole_refused() -> Result<()> {
+        let (blocked, allowed) = sinkhole_responses(Sinkhole::Refused)?;
+
+        assert_eq!(ResponseCode::Refused, blocked.response_code());
+        assert!(blocked.answers().is_empty());
+        assert_eq!(ResponseCode::NoError, allowed.response_code());
+        assert_eq!(1, allowed.answers().len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn wildcard_answers_use_query_name() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("*.example.com", Record::from_ip_v4("1.2.3.4")?)?;
+        server.add_record("www.example.com", Record::from_ip_v4("192.0.2.80")?)?;
+        server.add_record("sub.example.com", Record::from_mx(10, &"mail.example.com".parse()?))?;
+
+        let response = Response::try_from(&respond(&server, &query_for("foo.example.com", RRType::A)?)?[..])?;
+        assert_eq!(vec![Answer::new(&"foo.example.com".parse()?, &Record::from_ip_v4("1.2.3.4")?)], response.answers());
+
+        let nested = server.lookup(&"a.b.example.com".parse()?, &QType::RRType(RRType::A));
+        assert_eq!(vec![Record::from_ip_v4("1.2.3.4")?], nested);
+
+        let exact = server.lookup(&"www.example.com".parse()?, &QType::RRType(RRType::A));
+        assert_eq!(vec![Record::from_ip_v4("192.0.2.80")?], exact);
+        assert!(server.lookup(&"sub.example.com".parse()?, &QType::RRType(RRType::A)).is_empty());
+        assert!(server.lookup(&"x.sub.example.com".parse()?, &QType::RRType(RRType::A)).is_empty());
+        assert!(server.lookup(&"example.com".parse()?, &QType::RRType(RRType::A)).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn wildcard_cnames_are_chased() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("*.example.com", Record::from_cname(&"target.example.com".parse()?))?;
+        server.add_record("target.example.com", Record::from_ip_v4("192.0.2.7")?)?;
+
+        let response = Response::try_from(&respond(&server, &query_for("foo.example.com", RRType::A)?)?[..])?;
+        assert_eq!(vec![
+            Answer::new(&"foo.example.com".parse()?, &Record::from_cname(&"target.example.com".parse()?)),
+            Answer::new(&"target.example.com".parse()?, &Record::from_ip_v4("192.0.2.7")?),
+        ], response.answers());
+
+        Ok(())
+    }
+
+    #[test]
+    fn views_answer_by_source() -> Result<()> {
+        let mut server = ServerBuilder::default()
+            .port(0)
+            .view("internal", "10.0.0.0/8")
+            .view("lab", "192.168.0.0/16")
+            .build()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+        server.add_record("intranet.example.com", Record::from_ip_v4("203.0.113.10")?)?;
+        server.add_record_to_view("internal", "intranet.example.com", Record::from_ip_v4("10.1.2.3")?)?;
+        assert!(server.add_record_to_view("dmz", "intranet.example.com", Record::from_ip_v4("10.9.9.9")?).is_err());
+
+        let query = query_for("intranet.example.com", RRType::A)?;
+        let answer = |source: &str| -> Result<Option<String>> {
+            let bytes = server.process_datagram_bytes(&query, source.parse()?)?;
+            Ok(Response::try_from(&bytes[..])?.answers()[0].record().as_ipv4())
+        };
+
+        assert_eq!(Some("10.1.2.3".into()), answer("10.20.30.40")?);
+        assert_eq!(Some("203.0.113.10".into()), answer("198.51.100.7")?);
+        assert_eq!(Some("203.0.113.10".into()), answer("192.168.1.1")?);
+
+        let fallback = server.process_datagram_bytes(CODECRAFTERS_QUERY, "10.20.30.40".parse()?)?;
+        assert_eq!(Some("8.8.8.8".into()), Response::try_from(&fallback[..])?.answers()[0].record().as_ipv4());
+
+        Ok(())
+    }
+
+    #[test]
+    fn added_names_are_validated() -> Result<()> {
+        let mut server = ServerBuilder::default().port(0).view("internal", "10.0.0.0/8").build()?;
+        let long_label = "a".repeat(64);
+        let wide_label = "é".repeat(32);
+
+        server.add_record("codecrafters.io.", Record::from_ip_v4("8.8.8.8")?)?;
+        assert!(server.add_record(&format!("{long_label}.example.org"), Record::from_ip_v4("192.0.2.1")?).is_err());
+        assert!(server.add_record(&format!("{wide_label}.example.org"), Record::from_ip_v4("192.0.2.1")?).is_err());
+        assert!(server.add_record("www..example.org", Record::from_ip_v4("192.0.2.1")?).is_err());
+        assert!(server.add_blocklist([long_label.as_str()].into_iter()).is_err());
+        assert!(server.add_record_to_view("internal", &long_label, Record::from_ip_v4("10.1.2.3")?).is_err());
+
+        let response = Response::try_from(&respond(&server, CODECRAFTERS_QUERY)?[..])?;
+        assert_eq!(Some("8.8.8.8".into()), response.answers()[0].record().as_ipv4());
+
+        Ok(())
+    }
+
+    #[test]
+    fn strict_parsing_rejects_count_mismatch() -> Result<()> {
+        let mut server = ServerBuilder::default().port(0).set_strict().build()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+        let mut query = CODECRAFTERS_QUERY.to_vec();
+        query[5] = 2;
+
+        assert_eq!(ResponseCode::FormatError, Response::try_from(&respond(&server, &query)?[..])?.response_code());
+        assert_eq!(CODECRAFTERS_RESPONSE, respond(&server, CODECRAFTERS_QUERY)?);
+
+        let mut lenient = test_server()?;
+        lenient.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+        assert_eq!(1, Response::try_from(&respond(&lenient, &query)?[..])?.answers().len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn repeated_serve_calls_reuse_buffers() -> Result<()> {
+        let mut server = test_server()?;
+        server.add_record("codecrafters.io", Record::from_ip_v4("8.8.8.8")?)?;
+        let client = UdpSocket::bind("127.0.0.1:0")?;
+        client.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let mut counts = vec![];
+        let mut buf = [0; 512];
+        for _ in 0..5 {
+            client.send_to(CODECRAFTERS_QUERY, server.local_addr()?)?;
+            let (served, count) = allocations(|| server.serve());
+            served?;
+            counts.push(count);
+
+            let (size, _) = client.recv_from(&mut buf)?;
+            assert_eq!(CODECRAFTERS_RESPONSE, &buf[..size]);
+        }
+
+        assert!(counts[1..].iter().all(|&count| count <= counts[0]), "allocations per call: {counts:?}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn refused_queries_reuse_buffers() -> Result<()> {
+        let server = ServerBuilder::default().port(0).allow("10.0.0.0/8").build()?;
+        let client = UdpSocket::bind("127.0.0.1:0")?;
+        client.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let send_buffer = || server.buffers.lock().unwrap().1.as_ptr();
+        let before = send_buffer();
+
+        let mut buf = [0; 512];
+        for _ in 0..3 {
+            client.send_to(CODECRAFTERS_QUERY, server.local_addr()?)?;
+            server.serve()?;
+
+            let (size, _) = client.recv_from(&mut buf)?;
+            assert_eq!(5, buf[3] & 0x0f);
+            assert_eq!(size, server.buffers.lock().unwrap().1.len());
+            assert_eq!(before, send_buffer());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn chaos_version_query() -> Result<()> {
+        let chaos = |name: &str| -> Result<Vec<u8>> {
+            Query::builder()
+                .id(0x4242)
+                .questions(vec![Question::new(name.parse()?, QType::RRType(RRType::TXT), QClass::RRClass(RRClass::CH))])
+                .build()
+                .to_vec()
+        };
+
+        let server = ServerBuilder::default().port(0).version_string("dns 1.2.3").build()?;
+        let response = Response::try_from(&respond(&server, &chaos("VERSION.bind")?)?[..])?;
+        assert_eq!(ResponseCode::NoError, response.response_code());
+        assert_eq!(1, response.answers().len());
+        assert_eq!(&RRClass::CH, response.answers()[0].record().rrclass());
+        assert_eq!(vec!["dns 1.2.3"], response.answers()[0].record().txt_strings()?);
+
+        let default = test_server()?;
+        let response = Response::try_from(&respond(&default, &chaos("hostname.bind")?)?[..])?;
+        assert_eq!(vec![DEFAULT_VERSION_STRING], response.answers()[0].record().txt_strings()?);
+
+        let response = Response::try_from(&respond(&default, &query_for("version.bind", RRType::TXT)?)?[..])?;
+        assert!(response.answers().is_empty());
+
+        Ok(())
     }
 }
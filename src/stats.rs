@@ -0,0 +1,146 @@
+use std::{collections::HashMap, sync::{atomic::{AtomicU64, Ordering}, Mutex}};
+
+use crate::common::{RRType, ResponseCode};
+
+const RCODE_SLOTS: usize = 17;
+const RRTYPE_SLOTS: usize = 256;
+
+fn rcode_slot(rcode: &ResponseCode) -> usize {
+    u8::from(rcode.clone()) as usize
+}
+
+/// Types below `RRTYPE_SLOTS` get a fixed counter; rarer high types are kept by value.
+fn rrtype_slot(rrtype: &RRType) -> Result<usize, u16> {
+    match u16::from(rrtype.clone()) {
+        value if (value as usize) < RRTYPE_SLOTS => Ok(value as usize),
+        value => Err(value),
+    }
+}
+
+#[derive(Debug)]
+pub struct Stats {
+    queries: AtomicU64,
+    responses: [AtomicU64; RCODE_SLOTS],
+    answers: [AtomicU64; RRTYPE_SLOTS],
+    high_answers: Mutex<HashMap<u16, u64>>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    forwards: AtomicU64,
+    upstream_timeouts: AtomicU64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            queries: AtomicU64::new(0),
+            responses: std::array::from_fn(|_| AtomicU64::new(0)),
+            answers: std::array::from_fn(|_| AtomicU64::new(0)),
+            high_answers: Mutex::new(HashMap::new()),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            forwards: AtomicU64::new(0),
+            upstream_timeouts: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Stats {
+    pub(crate) fn query(&self) {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn response(&self, rcode: &ResponseCode) {
+        self.responses[rcode_slot(rcode)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn answer(&self, rrtype: &RRType) {
+        match rrtype_slot(rrtype) {
+            Ok(slot) => { self.answers[slot].fetch_add(1, Ordering::Relaxed); },
+            Err(value) => *self.high_answers.lock().unwrap().entry(value).or_default() += 1,
+        }
+    }
+
+    pub(crate) fn cache_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn cache_miss(&self) {
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn forward(&self) {
+        self.forwards.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn upstream_timeout(&self) {
+        self.upstream_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        StatsSnapshot {
+            queries: load(&self.queries),
+            responses: self.responses.each_ref().map(load),
+            answers: self.answers.each_ref().map(load),
+            high_answers: self.high_answers.lock().unwrap().clone(),
+            cache_hits: load(&self.cache_hits),
+            cache_misses: load(&self.cache_misses),
+            forwards: load(&self.forwards),
+            upstream_timeouts: load(&self.upstream_timeouts),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsSnapshot {
+    pub queries: u64,
+    responses: [u64; RCODE_SLOTS],
+    answers: [u64; RRTYPE_SLOTS],
+    high_answers: HashMap<u16, u64>,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub forwards: u64,
+    pub upstream_timeouts: u64,
+}
+
+impl StatsSnapshot {
+    pub fn responses(&self, rcode: &ResponseCode) -> u64 {
+        self.responses[rcode_slot(rcode)]
+    }
+
+    pub fn answers(&self, rrtype: &RRType) -> u64 {
+        match rrtype_slot(rrtype) {
+            Ok(slot) => self.answers[slot],
+            Err(value) => self.high_answers.get(&value).copied().unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_are_snapshotted() {
+        let stats = Stats::default();
+        stats.query();
+        stats.query();
+        stats.response(&ResponseCode::NoError);
+        stats.response(&ResponseCode::BadVersion);
+        stats.answer(&RRType::AAAA);
+        stats.answer(&RRType::Unknown(4000));
+
+        let snapshot = stats.snapshot();
+        assert_eq!(2, snapshot.queries);
+        assert_eq!(1, snapshot.responses(&ResponseCode::NoError));
+        assert_eq!(1, snapshot.responses(&ResponseCode::BadVersion));
+        assert_eq!(0, snapshot.responses(&ResponseCode::Refused));
+        assert_eq!(1, snapshot.answers(&RRType::AAAA));
+        assert_eq!(1, snapshot.answers(&RRType::Unknown(4000)));
+        assert_eq!(0, snapshot.answers(&RRType::Unknown(4001)));
+        assert_eq!(0, snapshot.answers(&RRType::Unknown(0)));
+        assert_eq!(0, snapshot.forwards);
+        assert_eq!(0, snapshot.upstream_timeouts);
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::{ErrorKind, Read, Write};
+
+use anyhow::{Result, bail};
+
+pub fn read_framed(reader: &mut impl Read) -> Result<Option<Vec<u8>>> {
+    let mut prefix = [0; 2];
+
+    match reader.read_exact(&mut prefix) {
+        Ok(()) => {},
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+
+    let mut message = vec![0; u16::from_be_bytes(prefix) as usize];
+    if let Err(err) = reader.read_exact(&mut message) {
+        bail!("Truncated TCP message: {err}");
+    }
+
+    Ok(Some(message))
+}
+
+pub fn write_framed(writer: &mut impl Write, message: &[u8]) -> Result<()> {
+    let Ok(length) = u16::try_from(message.len()) else {
+        bail!("Message too long for TCP framing: {} bytes", message.len())
+    };
+
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(message)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn write_and_read_framed() -> Result<()> {
+        let mut stream = vec![];
+        write_framed(&mut stream, b"abc")?;
+        write_framed(&mut stream, b"")?;
+
+        assert_eq!(b"\x00\x03abc\x00\x00", &stream[..]);
+
+        let mut reader = Cursor::new(stream);
+        assert_eq!(Some(b"abc".to_vec()), read_framed(&mut reader)?);
+        assert_eq!(Some(vec![]), read_framed(&mut reader)?);
+        assert_eq!(None, read_framed(&mut reader)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_truncated_frame() {
+        let mut reader = Cursor::new(b"\x00\x05ab".to_vec());
+
+        assert!(read_framed(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use std::io::BufRead;
+
+use anyhow::{Context, Result, bail};
+
+use crate::common::{Name, RRClass, RRType, Record};
+
+fn tokenize(line: &str) -> Result<Vec<String>> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            ';' => break,
+            c if c.is_whitespace() => { chars.next(); },
+            '"' => {
+                chars.next();
+                let mut token = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => token.push(unescape(&mut chars)?),
+                        Some(c) => token.push(c),
+                        None => bail!("Unterminated quoted string"),
+                    }
+                }
+                tokens.push(token);
+            },
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(token);
+            },
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn unescape(chars: &mut impl Iterator<Item = char>) -> Result<char> {
+    match chars.next() {
+        Some(c) if c.is_ascii_digit() => {
+            let digits = [Some(c), chars.next(), chars.next()].into_iter().collect::<Option<String>>();
+            match digits.and_then(|d| d.parse::<u8>().ok()) {
+                Some(value) if value.is_ascii() => Ok(char::from(value)),
+                _ => bail!("Invalid \\DDD escape"),
+            }
+        },
+        Some(c) => Ok(c),
+        None => bail!("Unterminated quoted string"),
+    }
+}
+
+fn parse_generic(rrtype: &RRType, data: &[String]) -> Result<Record> {
+    let Some((length, hex)) = data.split_first() else {
+        bail!("Missing {rrtype} data length")
+    };
+    let hex = hex.concat();
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        bail!("Invalid {rrtype} hex data")
+    }
+
+    let bytes = (0..hex.len()).step_by(2)
+                              .map(|k| u8::from_str_radix(&hex[k..k + 2], 16))
+                              .collect::<Result<Vec<_>, _>>()?;
+    if bytes.len() != length.parse::<usize>()? {
+        bail!("{rrtype} data doesn't match its length")
+    }
+
+    Ok(Record::new(rrtype.clone(), RRClass::IN, bytes))
+}
+
+fn qualify(name: &str, origin: Option<&Name>) -> Result<Name> {
+    match (name, origin) {
+        ("@", Some(origin)) => Ok(origin.clone()),
+        ("@", None) => bail!("'@' used without an $ORIGIN"),
+        (name, _) if name.ends_with('.') => name.parse(),
+        (name, Some(origin)) => {
+            let mut labels = name.parse::<Name>()?.labels().clone();
+            labels.extend(origin.labels().iter().cloned());
+            Name::try_new(labels)
+        },
+        (name, None) => name.parse(),
+    }
+}
+
+fn parse_record(name: Name, tokens: &[String], origin: Option<&Name>, default_ttl: Option<u32>) -> Result<(Name, Record)> {
+    let mut rest = tokens;
+    let mut ttl = default_ttl;
+
+    if let Some(value) = rest.first().and_then(|t| t.parse::<u32>().ok()) {
+        ttl = Some(value);
+        rest = &rest[1..];
+    }
+
+    let mut rrclass = RRClass::IN;
+    if let Some(class) = rest.first().and_then(|t| t.parse::<RRClass>().ok()) {
+        rrclass = class;
+        rest = &rest[1..];
+    }
+
+    let Some((rrtype, data)) = rest.split_first() else {
+        bail!("Missing record type")
+    };
+    let rrtype: RRType = rrtype.parse()?;
+    let field = |k: usize| data.get(k).map(String::as_str).with_context(|| format!("Missing {rrtype} data"));
+
+    let record = match rrtype {
+        _ if data.first().is_some_and(|t| t == "\\#") => parse_generic(&rrtype, &data[1..])?,
+        RRType::A => Record::from_ip_v4(field(0)?)?,
+        RRType::AAAA => Record::from_ip_v6(field(0)?)?,
+        RRType::CNAME => Record::from_cname(&qualify(field(0)?, origin)?),
+        RRType::NS => Record::from_ns(&qualify(field(0)?, origin)?),
+        RRType::PTR => Record::from_ptr(&qualify(field(0)?, origin)?),
+        RRType::MX => Record::from_mx(field(0)?.parse()?, &qualify(field(1)?, origin)?),
+        RRType::TXT => Record::from_txt(&data.iter().map(String::as_str).collect::<Vec<_>>())?,
+        RRType::SRV => Record::from_srv(field(0)?.parse()?, field(1)?.parse()?, field(2)?.parse()?, &qualify(field(3)?, origin)?),
+        RRType::SOA => Record::from_soa(
+            &qualify(field(0)?, origin)?,
+            &qualify(field(1)?, origin)?,
+            field(2)?.parse()?,
+            field(3)?.parse()?,
+            field(4)?.parse()?,
+            field(5)?.parse()?,
+            field(6)?.parse()?,
+        ),
+        other => bail!("Unsupported record type {other}"),
+    };
+
+    let record = record.with_class(rrclass);
+    Ok((name, match ttl {
+        Some(ttl) => record.with_ttl(ttl),
+        None => record,
+    }))
+}
+
+pub fn parse_zone(reader: impl BufRead) -> Result<Vec<(Name, Record)>> {
+    let mut origin = None;
+    let mut default_ttl = None;
+    let mut owner = None;
+    let mut records = vec![];
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let context = || format!("zone file line {}", number + 1);
+        let tokens = tokenize(&line).with_context(context)?;
+
+        match tokens.first().map(String::as_str) {
+            None => continue,
+            Some("$ORIGIN") => {
+                let value = tokens.get(1).with_context(context)?;
+                origin = Some(qualify(value, origin.as_ref()).with_context(context)?);
+            },
+            Some("$TTL") => {
+                let value = tokens.get(1).with_context(context)?;
+                default_ttl = Some(value.parse().with_context(context)?);
+            },
+            // Like BIND, an indented record belongs to the previous record's owner
+            Some(_) if line.starts_with(char::is_whitespace) => {
+                let name = owner.clone().context("Indented record without a previous owner").with_context(context)?;
+                records.push(parse_record(name, &tokens, origin.as_ref(), default_ttl).with_context(context)?);
+            },
+            Some(name) => {
+                let name = qualify(name, origin.as_ref()).with_context(context)?;
+                owner = Some(name.clone());
+                records.push(parse_record(name, &tokens[1..], origin.as_ref(), default_ttl).with_context(context)?);
+            },
+        }
+    }
+
+    Ok(records)
+}
+
+pub fn parse_hosts(reader: impl BufRead) -> Result<Vec<(Name, Record)>> {
+    let mut records = vec![];
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let context = || format!("hosts file line {}", number + 1);
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+
+        let Some(address) = fields.next() else {
+            continue;
+        };
+        let record = if address.contains(':') {
+            Record::from_ip_v6(address)
+        } else {
+            Record::from_ip_v4(address)
+        }.with_context(context)?;
+
+        for host in fields {
+            records.push((host.parse().with_context(context)?, record.clone()));
+        }
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    static ZONE: &str = "\
+$ORIGIN example.com.
+; a small test zone
+@       3600 IN SOA ns1 admin 2024010101 7200 3600 1209600 300
+@       3600 IN NS  ns1
+ns1     300  IN A   192.0.2.1
+@            IN A   192.0.2.2 ; default TTL
+www     300  IN CNAME @
+mail.other.org. 300 IN AAAA 2001:db8::1
+@       300  IN MX  10 mail.other.org.
+@       300  IN TXT \"v=spf1 -all\" \"second string\"
+";
+
+    #[test]
+    fn parse_small_zone() -> Result<()> {
+        let records = parse_zone(Cursor::new(ZONE))?;
+        let apex: Name = "example.com".parse()?;
+
+        assert_eq!(8, records.len());
+        assert_eq!((apex.clone(), RRType::SOA), (records[0].0.clone(), records[0].1.rrtype().clone()));
+        assert_eq!("ns1.example.com".parse::<Name>()?, records[0].1.soa()?.mname);
+        assert_eq!("ns1.example.com".parse::<Name>()?, records[1].1.ns_name()?);
+        assert_eq!(("ns1.example.com".parse()?, Record::from_ip_v4("192.0.2.1")?.with_ttl(300)), records[2]);
+        assert_eq!((apex.clone(), Record::from_ip_v4("192.0.2.2")?), records[3]);
+        assert_eq!(apex, records[4].1.cname_target()?);
+        assert_eq!(("mail.other.org".parse()?, Record::from_ip_v6("2001:db8::1")?.with_ttl(300)), records[5]);
+        assert_eq!("mail.other.org".parse::<Name>()?, records[6].1.mx_exchange()?);
+        assert_eq!(vec!["v=spf1 -all", "second string"], records[7].1.txt_strings()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn indented_records_inherit_owner() -> Result<()> {
+        let records = parse_zone(Cursor::new("$ORIGIN example.com.\nwww 300 IN A 192.0.2.1\n   IN A 192.0.2.2\n\t300 IN AAAA 2001:db8::1\n"))?;
+        let www: Name = "www.example.com".parse()?;
+
+        assert_eq!(vec![
+            (www.clone(), Record::from_ip_v4("192.0.2.1")?.with_ttl(300)),
+            (www.clone(), Record::from_ip_v4("192.0.2.2")?),
+            (www, Record::from_ip_v6("2001:db8::1")?.with_ttl(300)),
+        ], records);
+
+        let err = parse_zone(Cursor::new("$ORIGIN example.com.\n   IN A 192.0.2.2\n")).unwrap_err();
+        assert_eq!("zone file line 2", err.to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn escapes_classes_and_generic_rdata() -> Result<()> {
+        let zone = "\
+host.example.com. 300 IN TXT \"say \\\"hi\\\"\" \"back\\\\slash\\009tab\"
+host.example.com. 300 CH TXT \"chaos\"
+host.example.com. 300 IN TYPE65280 \\# 3 ab cdef
+host.example.com. 300 IN A \\# 4 c0000201
+";
+        let records = parse_zone(Cursor::new(zone))?;
+
+        assert_eq!(vec!["say \"hi\"", "back\\slash\ttab"], records[0].1.txt_strings()?);
+        assert_eq!(&RRClass::CH, records[1].1.rrclass());
+        assert_eq!(Record::new(RRType::Unknown(65280), RRClass::IN, vec![0xab, 0xcd, 0xef]).with_ttl(300), records[2].1);
+        assert_eq!(Record::from_ip_v4("192.0.2.1")?.with_ttl(300), records[3].1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_hosts_file() -> Result<()> {
+        let hosts = "# static hosts\n\n127.0.0.1 localhost\n192.168.1.10 nas nas.lan # storage\n::1 localhost ip6-localhost\n";
+        let records = parse_hosts(Cursor::new(hosts))?;
+
+        assert_eq!(vec![
+            ("localhost".parse()?, Record::from_ip_v4("127.0.0.1")?),
+            ("nas".parse()?, Record::from_ip_v4("192.168.1.10")?),
+            ("nas.lan".parse()?, Record::from_ip_v4("192.168.1.10")?),
+            ("localhost".parse()?, Record::from_ip_v6("::1")?),
+            ("ip6-localhost".parse()?, Record::from_ip_v6("::1")?),
+        ], records);
+        assert!(parse_hosts(Cursor::new("300.0.0.1 broken\n")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn default_ttl_and_errors() -> Result<()> {
+        let records = parse_zone(Cursor::new("$TTL 900\nhost.example.com. IN A 10.0.0.1\n"))?;
+        assert_eq!(900, records[0].1.ttl());
+
+        assert!(parse_zone(Cursor::new("@ IN A 10.0.0.1\n")).is_err());
+        for address in ["1.2.3", "1.2.3.4.5", "1.2.3.256"] {
+            let err = parse_zone(Cursor::new(format!("$TTL 900\nhost. IN A {address}\n"))).unwrap_err();
+            assert_eq!("zone file line 2", err.to_string());
+        }
+        assert!(parse_zone(Cursor::new("host. IN HINFO x y\n")).is_err());
+        assert!(parse_zone(Cursor::new("host. IN MX 10\n")).is_err());
+        assert!(parse_zone(Cursor::new("host. IN TXT \"unterminated\n")).is_err());
+        assert!(parse_zone(Cursor::new("host. IN TYPE65280 \\# 3 abcd\n")).is_err());
+        assert!(parse_zone(Cursor::new("host. IN TYPE65280 \\# 2 abcz\n")).is_err());
+
+        Ok(())
+    }
+}